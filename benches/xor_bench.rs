@@ -1,7 +1,7 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
 
 use rand::{prelude::random, rngs::SmallRng, Rng, SeedableRng};
-use xorfilter::Xor8;
+use xorfilter::{BinaryFuse8Builder, Xor8Builder};
 
 use std::collections::hash_map::RandomState;
 
@@ -18,9 +18,9 @@ fn bench_populate_keys_100000(c: &mut Criterion) {
 
     c.bench_function("populate_keys_100000", |b| {
         b.iter(|| {
-            let mut filter = Xor8::<RandomState>::new();
-            filter.populate_keys(&keys);
-            filter.build();
+            let mut builder = Xor8Builder::<RandomState>::new();
+            builder.populate_keys(&keys);
+            builder.build()
         })
     });
 }
@@ -38,8 +38,7 @@ fn bench_build_keys_100000(c: &mut Criterion) {
 
     c.bench_function("bench_build_keys_100000", |b| {
         b.iter(|| {
-            let mut filter = Xor8::<RandomState>::new();
-            filter.build_keys(&keys);
+            Xor8Builder::<RandomState>::new().build_keys(&keys)
         })
     });
 }
@@ -57,9 +56,9 @@ fn bench_populate_100000(c: &mut Criterion) {
 
     c.bench_function("bench_populate_100000", |b| {
         b.iter(|| {
-            let mut filter = Xor8::<RandomState>::new();
-            filter.populate(&keys);
-            filter.build();
+            let mut builder = Xor8Builder::<RandomState>::new();
+            builder.populate(&keys);
+            builder.build()
         })
     });
 }
@@ -77,9 +76,9 @@ fn bench_insert_100000(c: &mut Criterion) {
 
     c.bench_function("bench_insert_100000", |b| {
         b.iter(|| {
-            let mut filter = Xor8::<RandomState>::new();
-            keys.iter().for_each(|key| filter.insert(key));
-            filter.build();
+            let mut builder = Xor8Builder::<RandomState>::new();
+            keys.iter().for_each(|key| builder.insert(key));
+            builder.build()
         })
     });
 }
@@ -96,10 +95,9 @@ fn bench_contains_100000(c: &mut Criterion) {
     }
 
     let filter = {
-        let mut filter = Xor8::<RandomState>::new();
-        filter.populate(&keys);
-        filter.build();
-        filter
+        let mut builder = Xor8Builder::<RandomState>::new();
+        builder.populate(&keys);
+        builder.build()
     };
 
     let mut n = 0;
@@ -123,10 +121,9 @@ fn bench_contains_key_100000(c: &mut Criterion) {
     }
 
     let filter = {
-        let mut filter = Xor8::<RandomState>::new();
-        filter.populate(&keys);
-        filter.build();
-        filter
+        let mut builder = Xor8Builder::<RandomState>::new();
+        builder.populate(&keys);
+        builder.build()
     };
 
     let mut n = 0;
@@ -138,6 +135,84 @@ fn bench_contains_key_100000(c: &mut Criterion) {
     });
 }
 
+// filter larger than cache, lookups are bound by cache misses.
+fn bench_contains_keys_batch_10000000(c: &mut Criterion) {
+    let seed: u128 = random();
+    let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+    let testsize = 10_000_000;
+    let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+    keys.resize(testsize, Default::default());
+    for key in keys.iter_mut() {
+        *key = rng.gen();
+    }
+
+    let filter = Xor8Builder::<RandomState>::new().build_keys(&keys);
+
+    let probes: Vec<u64> = (0..10_000).map(|_| rng.gen()).collect();
+    let mut result = vec![false; probes.len()];
+
+    let mut group = c.benchmark_group("contains_keys_10000000");
+    group.throughput(Throughput::Elements(probes.len() as u64));
+    group.bench_function("contains_key", |b| {
+        b.iter(|| {
+            for (key, res) in probes.iter().zip(result.iter_mut()) {
+                *res = filter.contains_key(*key);
+            }
+        })
+    });
+    group.bench_function("contains_keys_batch", |b| {
+        b.iter(|| filter.contains_keys_batch(&probes, &mut result))
+    });
+    group.finish();
+}
+
+fn bench_fuse8_populate_keys_100000(c: &mut Criterion) {
+    let seed: u128 = random();
+    let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+    let testsize = 100_000;
+    let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+    keys.resize(testsize, Default::default());
+    for key in keys.iter_mut() {
+        *key = rng.gen();
+    }
+
+    c.bench_function("fuse8_populate_keys_100000", |b| {
+        b.iter(|| {
+            let mut builder = BinaryFuse8Builder::<RandomState>::new();
+            builder.populate_keys(&keys);
+            builder.build()
+        })
+    });
+}
+
+fn bench_fuse8_contains_key_100000(c: &mut Criterion) {
+    let seed: u128 = random();
+    let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+    let testsize = 100_000;
+    let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+    keys.resize(testsize, Default::default());
+    for key in keys.iter_mut() {
+        *key = rng.gen();
+    }
+
+    let filter = {
+        let mut builder = BinaryFuse8Builder::<RandomState>::new();
+        builder.populate(&keys);
+        builder.build()
+    };
+
+    let mut n = 0;
+    c.bench_function("bench_fuse8_contains_key_100000", |b| {
+        b.iter(|| {
+            filter.contains_key(keys[n % keys.len()]);
+            n += 1;
+        })
+    });
+}
+
 criterion_group!(
     benches,
     bench_populate_keys_100000,
@@ -145,7 +220,10 @@ criterion_group!(
     bench_populate_100000,
     bench_insert_100000,
     bench_contains_100000,
-    bench_contains_key_100000
+    bench_contains_key_100000,
+    bench_contains_keys_batch_10000000,
+    bench_fuse8_populate_keys_100000,
+    bench_fuse8_contains_key_100000
 );
 
 criterion_main!(benches);
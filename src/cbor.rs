@@ -0,0 +1,90 @@
+use crate::serde_impl::Repr;
+use crate::{Error, Fingerprint, SerializableHasher, XorFilter};
+
+impl<F, H> XorFilter<F, H>
+where
+    F: Fingerprint,
+    H: SerializableHasher,
+{
+    /// Encode filter in CBOR, as a map of `seed` and `block_length`
+    /// integers, `hasher` and `hasher_params` byte-strings identifying the
+    /// hash builder, and `finger_prints` byte-string holding big-endian
+    /// encoded fingerprints.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::with_capacity(64 + (self.finger_prints.len() * F::SIZE));
+        ciborium::ser::into_writer(&Repr::from_filter(self), &mut buf)
+            .map_err(|err| Error::Cbor(err.to_string()))?;
+        Ok(buf)
+    }
+
+    /// Decode filter encoded by [XorFilter::to_cbor], validating it like
+    /// [XorFilter::from_bytes]. Bytes following the CBOR item are ignored.
+    pub fn from_cbor(buf: &[u8]) -> Result<Self, Error> {
+        // reading from `buf` fails only when the CBOR item is truncated,
+        // hence all errors, including io errors, mean malformed data.
+        let repr: Repr =
+            ciborium::de::from_reader(buf).map_err(|err| Error::Cbor(err.to_string()))?;
+        XorFilter::from_repr(repr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{splitmix64, BuildHasherDefault, Xor16, Xor8, Xor8Builder};
+    use ciborium::value::Value;
+    use rand::prelude::random;
+
+    #[test]
+    fn test_cbor() {
+        let mut seed: u64 = random();
+        println!("test_cbor seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = Xor8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+
+        let buf = filter.to_cbor().unwrap();
+        let filter_read = Xor8::<BuildHasherDefault>::from_cbor(&buf).unwrap();
+        assert!(filter_read == filter, "Filter unequals after cbor");
+        for key in keys.iter() {
+            assert!(filter_read.contains_key(*key), "key {} not present", key);
+        }
+
+        // fields are typed, as seen by other CBOR decoders.
+        let value: Value = ciborium::de::from_reader(buf.as_slice()).unwrap();
+        let fields: Vec<(String, Value)> = value
+            .into_map()
+            .unwrap()
+            .into_iter()
+            .map(|(k, v)| (k.into_text().unwrap(), v))
+            .collect();
+        let field =
+            |name: &str| fields.iter().find(|(k, _)| k == name).unwrap().1.clone();
+        assert_eq!(field("seed"), Value::Integer(filter.seed.into()));
+        assert_eq!(
+            field("block_length"),
+            Value::Integer(filter.block_length.into())
+        );
+        assert_eq!(field("hasher"), Value::Bytes(b"SD13".to_vec()));
+        assert_eq!(
+            field("finger_prints"),
+            Value::Bytes(filter.finger_prints.clone())
+        );
+
+        match Xor16::<BuildHasherDefault>::from_cbor(&buf) {
+            Err(Error::BlockLengthMismatch { .. }) => (),
+            res => panic!("unexpected {:?}", res.err()),
+        }
+        for n in [0, 1, 10, buf.len() / 2, buf.len() - 1] {
+            match Xor8::<BuildHasherDefault>::from_cbor(&buf[..n]) {
+                Err(err @ Error::Cbor(_)) => assert!(err.is_corrupt(), "{}", err),
+                res => panic!("unexpected {} {:?}", n, res.err()),
+            }
+        }
+        match Xor8::<BuildHasherDefault>::from_cbor(&[0xff, 0x00]) {
+            Err(err @ Error::Cbor(_)) => assert!(err.is_corrupt(), "{}", err),
+            res => panic!("unexpected {:?}", res.err()),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{error, fmt, io};
+
+/// Error type for all fallible operations in this crate.
+///
+/// Errors from serialized bytes and files, other than [Error::Io], mean
+/// that the data is not a valid filter of the requested type. Use
+/// [Error::is_corrupt] to tell damaged data apart from data this version
+/// of the crate cannot read.
+///
+/// Error is non-exhaustive, variants are added by new versions and by
+/// optional features, like `Cbor` with the `cbor` feature.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Failed to read or write the underlying file.
+    Io(io::Error),
+    /// Bytes don't start with the signature of the requested filter type.
+    InvalidSignature([u8; 3]),
+    /// Filter serialized in a format version not supported by this crate.
+    UnsupportedVersion(u8),
+    /// Serialized bytes are shorter than expected.
+    Truncated { expected: usize, actual: usize },
+    /// Serialized bytes continue after the end of the filter.
+    TrailingBytes(usize),
+    /// Checksum in the header does not match the serialized data.
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// Serialized filter with zero block length, that cannot be queried.
+    ZeroBlockLength,
+    /// Number of fingerprints does not match `3 * block_length`.
+    BlockLengthMismatch { block_length: u32, fp_len: usize },
+    /// Segment length is not a power of two or is too large.
+    InvalidSegmentLength(u32),
+    /// Number of fingerprints does not match the segment layout.
+    InvalidSegmentCount { segment_count: u32, fp_len: usize },
+    /// Length of fingerprints overflows the address space.
+    Overflow,
+    /// Filter was serialized with a different hash function, refer to
+    /// [SerializableHasher](crate::SerializableHasher).
+    HasherMismatch { expected: [u8; 4], actual: [u8; 4] },
+    /// Parameters of the hash function are malformed.
+    InvalidHasherParams([u8; 4]),
+    /// Malformed or truncated CBOR encoding of filter.
+    #[cfg(feature = "cbor")]
+    Cbor(String),
+    /// Filter could not be constructed, refer to [BuildError].
+    Build(BuildError),
+}
+
+impl Error {
+    /// Return true if serialized data is damaged or malformed, as opposed
+    /// to I/O failures, unsupported versions, hasher mismatch and intact
+    /// filters of another type or fingerprint width.
+    pub fn is_corrupt(&self) -> bool {
+        match self {
+            // signature of a filter serialized by this crate.
+            Error::InvalidSignature(sig) => {
+                !matches!(sig, [b'^', b'T' | b'F' | b'Q', b'L' | b'2' | b'4'])
+            }
+            Error::Io(_)
+            | Error::UnsupportedVersion(_)
+            | Error::HasherMismatch { .. }
+            | Error::Build(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+
+        match self {
+            Io(err) => write!(f, "io error, {}", err),
+            InvalidSignature(sig) => write!(f, "invalid signature {:?}", sig),
+            UnsupportedVersion(ver) => write!(f, "unsupported version {}", ver),
+            Truncated { expected, actual } => {
+                write!(f, "truncated data, {} bytes of {}", actual, expected)
+            }
+            TrailingBytes(n) => write!(f, "{} trailing bytes", n),
+            ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum mismatch {:x} != {:x}", actual, expected)
+            }
+            ZeroBlockLength => write!(f, "zero block length"),
+            BlockLengthMismatch {
+                block_length,
+                fp_len,
+            } => write!(
+                f,
+                "block length {} mismatch with {} fingerprints",
+                block_length, fp_len
+            ),
+            InvalidSegmentLength(n) => write!(f, "invalid segment length {}", n),
+            InvalidSegmentCount {
+                segment_count,
+                fp_len,
+            } => write!(
+                f,
+                "segment count {} mismatch with {} fingerprints",
+                segment_count, fp_len
+            ),
+            Overflow => write!(f, "fingerprint length overflow"),
+            HasherMismatch { expected, actual } => write!(
+                f,
+                "hasher mismatch {:?} != {:?}",
+                String::from_utf8_lossy(actual),
+                String::from_utf8_lossy(expected)
+            ),
+            InvalidHasherParams(id) => {
+                write!(
+                    f,
+                    "invalid params for hasher {:?}",
+                    String::from_utf8_lossy(id)
+                )
+            }
+            #[cfg(feature = "cbor")]
+            Cbor(msg) => write!(f, "cbor error, {}", msg),
+            Build(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Build(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<BuildError> for Error {
+    fn from(err: BuildError) -> Error {
+        Error::Build(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        if let Error::Io(err) = err {
+            return err;
+        }
+        let kind = match &err {
+            Error::UnsupportedVersion(_) => io::ErrorKind::Unsupported,
+            Error::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            Error::Build(_) => io::ErrorKind::Other,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Error returned by `try_build` and `try_build_keys` when the filter could
+/// not be constructed within the configured number of attempts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildError {
+    /// Number of keys that could not be peeled in the last attempt.
+    pub unpeeled: usize,
+    /// Seeds tried, in the order of attempts.
+    pub seeds: Vec<u64>,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "failed to build filter after {} attempts, {} keys not peeled, seeds {:?}",
+            self.seeds.len(),
+            self.unpeeled,
+            self.seeds
+        )
+    }
+}
+
+impl error::Error for BuildError {}
@@ -0,0 +1,1027 @@
+use std::{
+    borrow::Cow,
+    convert::TryInto,
+    ffi, fs,
+    hash::{BuildHasher, Hash},
+    io::{Read, Write},
+    marker::PhantomData,
+};
+
+#[allow(unused_imports)]
+use std::collections::hash_map::RandomState;
+
+use crate::hasher::{make_hasher, read_hasher, write_hasher};
+use crate::{dedup_keys, mixsplit, splitmix64, DEFAULT_MAX_ATTEMPTS, DEFAULT_RNG_SEED};
+use crate::{BuildError, BuildHasherDefault, Error, Fingerprint, SerializableHasher};
+
+/// Upper bound on segment length, beyond this construction stops
+/// getting any faster.
+const MAX_SEGMENT_LENGTH: u32 = 262144;
+
+/// Type BinaryFuse is probabilistic data-structure to test membership of
+/// an element in a set, using binary fuse construction.
+///
+/// Refer to [Binary Fuse Filters: Fast and Smaller Than Xor
+/// Filters](https://arxiv.org/abs/2201.01174). Unlike [XorFilter], that
+/// spreads the fingerprint array into three blocks, BinaryFuse splits the
+/// array into many small segments and maps each key to `ARITY` consecutive
+/// segments. With 3-wise construction, the default, space overhead comes
+/// down to about 1.13x for sizeable sets, compared to 1.23x for
+/// [XorFilter], and improves cache locality while building the filter.
+/// 4-wise construction brings the space overhead further down to about
+/// 1.075x, at the cost of one more memory access per lookup.
+///
+/// BinaryFuse is parametrized over fingerprint type `F`, refer to
+/// [BinaryFuse8], [BinaryFuse16], [BinaryFuse4Wise8] and
+/// [BinaryFuse4Wise16], and over type `H` which is expected to implement
+/// [BuildHasher] trait, like [RandomState] and [BuildHasherDefault]. When
+/// applications want to serialize and de-serialize BinaryFuse, avoid
+/// using `RandomState`. `ARITY` can only be 3 or 4, other values fail to
+/// compile.
+///
+/// ```compile_fail
+/// use xorfilter::{BinaryFuseBuilder, BuildHasherDefault};
+///
+/// let builder = BinaryFuseBuilder::<u8, BuildHasherDefault, 5>::new();
+/// ```
+///
+/// BinaryFuse is immutable, use [BinaryFuseBuilder] to build it.
+///
+/// [XorFilter]: crate::XorFilter
+pub struct BinaryFuse<F, H = BuildHasherDefault, const ARITY: usize = 3>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    attempts: usize,
+    duplicates: usize,
+    hash_builder: H,
+    seed: u64,
+    segment_length: u32,
+    segment_length_mask: u32,
+    segment_count: u32,
+    segment_count_length: u32,
+    finger_prints: Vec<F>,
+}
+
+/// BinaryFuse with 8-bit fingerprints.
+///
+/// This implementation has a false positive rate of about 0.39%
+/// and a memory usage of less than 9.1 bits per entry for sizeable sets.
+pub type BinaryFuse8<H = BuildHasherDefault> = BinaryFuse<u8, H>;
+
+/// BinaryFuse with 16-bit fingerprints.
+///
+/// This implementation has a false positive rate of about 0.0015%
+/// and a memory usage of less than 18.1 bits per entry for sizeable sets.
+pub type BinaryFuse16<H = BuildHasherDefault> = BinaryFuse<u16, H>;
+
+/// BinaryFuse with 4-wise construction and 8-bit fingerprints.
+///
+/// This implementation has a false positive rate of about 0.39%
+/// and a memory usage of less than 8.7 bits per entry for sizeable sets.
+pub type BinaryFuse4Wise8<H = BuildHasherDefault> = BinaryFuse<u8, H, 4>;
+
+/// BinaryFuse with 4-wise construction and 16-bit fingerprints.
+///
+/// This implementation has a false positive rate of about 0.0015%
+/// and a memory usage of less than 17.3 bits per entry for sizeable sets.
+pub type BinaryFuse4Wise16<H = BuildHasherDefault> = BinaryFuse<u16, H, 4>;
+
+impl<F, H, const ARITY: usize> PartialEq for BinaryFuse<F, H, ARITY>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.seed == other.seed
+            && self.segment_length == other.segment_length
+            && self.segment_count == other.segment_count
+            && self.finger_prints == other.finger_prints
+    }
+}
+
+/// Type BinaryFuseBuilder collects keys and builds a [BinaryFuse].
+///
+/// Building consumes the builder and returns an immutable filter, refer to
+/// [BinaryFuse8Builder], [BinaryFuse16Builder], [BinaryFuse4Wise8Builder]
+/// and [BinaryFuse4Wise16Builder].
+#[derive(Clone)]
+pub struct BinaryFuseBuilder<F, H = BuildHasherDefault, const ARITY: usize = 3>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    keys: Vec<u64>,
+    max_attempts: usize,
+    rng_seed: u64,
+    hash_builder: H,
+    _fp: PhantomData<F>,
+}
+
+/// BinaryFuseBuilder for [BinaryFuse8].
+pub type BinaryFuse8Builder<H = BuildHasherDefault> = BinaryFuseBuilder<u8, H>;
+
+/// BinaryFuseBuilder for [BinaryFuse16].
+pub type BinaryFuse16Builder<H = BuildHasherDefault> = BinaryFuseBuilder<u16, H>;
+
+/// BinaryFuseBuilder for [BinaryFuse4Wise8].
+pub type BinaryFuse4Wise8Builder<H = BuildHasherDefault> = BinaryFuseBuilder<u8, H, 4>;
+
+/// BinaryFuseBuilder for [BinaryFuse4Wise16].
+pub type BinaryFuse4Wise16Builder<H = BuildHasherDefault> = BinaryFuseBuilder<u16, H, 4>;
+
+impl<F, H, const ARITY: usize> Default for BinaryFuseBuilder<F, H, ARITY>
+where
+    F: Fingerprint,
+    H: BuildHasher + Default,
+{
+    fn default() -> Self {
+        BinaryFuseBuilder::with_hasher(H::default())
+    }
+}
+
+impl<F, H, const ARITY: usize> BinaryFuseBuilder<F, H, ARITY>
+where
+    F: Fingerprint,
+    H: Default + BuildHasher,
+{
+    /// New BinaryFuseBuilder instance initialized with `DefaultHasher`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<F, H, const ARITY: usize> BinaryFuseBuilder<F, H, ARITY>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// New BinaryFuseBuilder instance initialized with supplied `hasher`.
+    pub fn with_hasher(hash_builder: H) -> Self {
+        let () = BinaryFuse::<F, H, ARITY>::ARITY_SUPPORTED;
+        BinaryFuseBuilder {
+            keys: Vec::default(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            rng_seed: DEFAULT_RNG_SEED,
+            hash_builder,
+            _fp: PhantomData,
+        }
+    }
+
+    /// Insert 64-bit digest of a single key. Digest for the key shall
+    /// be generated using the default-hasher or via hasher supplied via
+    /// [BinaryFuseBuilder::with_hasher] method.
+    pub fn insert<T: ?Sized + Hash>(&mut self, key: &T) {
+        let hashed_key = self.hash_builder.hash_one(key);
+        self.keys.push(hashed_key);
+    }
+
+    /// Populate 64-bit digests for collection of keys. Digest for the key
+    /// shall be generated using the default-hasher or via hasher supplied
+    /// via [BinaryFuseBuilder::with_hasher] method.
+    pub fn populate<T: Hash>(&mut self, keys: &[T]) {
+        keys.iter().for_each(|key| {
+            let hashed_key = self.hash_builder.hash_one(key);
+            self.keys.push(hashed_key);
+        })
+    }
+
+    /// Populate pre-compute 64-bit digests for keys.
+    pub fn populate_keys(&mut self, keys: &[u64]) {
+        self.keys.extend_from_slice(keys)
+    }
+
+    /// Set the maximum number of seeds tried by [BinaryFuseBuilder::try_build]
+    /// and [BinaryFuseBuilder::try_build_keys], defaults to
+    /// [DEFAULT_MAX_ATTEMPTS]. At least one seed is always tried, zero is
+    /// taken as 1.
+    pub fn set_max_attempts(&mut self, max_attempts: usize) -> &mut Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the initial state of the random number generator that seeds are
+    /// drawn from, defaults to [DEFAULT_RNG_SEED].
+    ///
+    /// Like [XorFilterBuilder::set_seed], the first attempt uses
+    /// `splitmix64(rng_seed)` and every retry uses the next number in the
+    /// splitmix64 stream, so the same keys and `rng_seed` always yield the
+    /// same filter. Refer to [BinaryFuse::attempts].
+    ///
+    /// [XorFilterBuilder::set_seed]: crate::XorFilterBuilder::set_seed
+    pub fn set_seed(&mut self, rng_seed: u64) -> &mut Self {
+        self.rng_seed = rng_seed;
+        self
+    }
+
+    /// Build the filter for keys inserted using [BinaryFuseBuilder::insert],
+    /// [BinaryFuseBuilder::populate] and [BinaryFuseBuilder::populate_keys].
+    ///
+    /// Construction is retried with a new seed until it succeeds. If an
+    /// attempt fails, duplicate digests are detected and removed, refer
+    /// to [BinaryFuse::duplicates]. Use [BinaryFuseBuilder::try_build] to
+    /// give up after a bounded number of attempts.
+    pub fn build(mut self) -> BinaryFuse<F, H, ARITY> {
+        let keys = std::mem::take(&mut self.keys);
+        self.build_keys_with(&keys, usize::MAX).unwrap()
+    }
+
+    /// Build the filter for pre-computed 64-bit digests for keys. Keys
+    /// inserted into the builder are ignored. Refer to
+    /// [BinaryFuseBuilder::build].
+    pub fn build_keys(self, keys: &[u64]) -> BinaryFuse<F, H, ARITY> {
+        self.build_keys_with(keys, usize::MAX).unwrap()
+    }
+
+    /// Same as [BinaryFuseBuilder::build], but give up after
+    /// [BinaryFuseBuilder::set_max_attempts] attempts. Clone the builder
+    /// to retry with the same keys.
+    pub fn try_build(mut self) -> Result<BinaryFuse<F, H, ARITY>, BuildError> {
+        let keys = std::mem::take(&mut self.keys);
+        let max_attempts = self.max_attempts;
+        self.build_keys_with(&keys, max_attempts)
+    }
+
+    /// Same as [BinaryFuseBuilder::build_keys], but give up after
+    /// [BinaryFuseBuilder::set_max_attempts] attempts.
+    pub fn try_build_keys(
+        self,
+        keys: &[u64],
+    ) -> Result<BinaryFuse<F, H, ARITY>, BuildError> {
+        let max_attempts = self.max_attempts;
+        self.build_keys_with(keys, max_attempts)
+    }
+
+    fn build_keys_with(
+        self,
+        keys: &[u64],
+        max_attempts: usize,
+    ) -> Result<BinaryFuse<F, H, ARITY>, BuildError> {
+        let mut filter = BinaryFuse {
+            attempts: 0,
+            duplicates: 0,
+            hash_builder: self.hash_builder,
+            seed: u64::default(),
+            segment_length: u32::default(),
+            segment_length_mask: u32::default(),
+            segment_count: u32::default(),
+            segment_count_length: u32::default(),
+            finger_prints: Vec::default(),
+        };
+        filter.build_keys_with(keys, max_attempts, self.rng_seed)?;
+        Ok(filter)
+    }
+}
+
+impl<F, H, const ARITY: usize> BinaryFuse<F, H, ARITY>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    // Evaluated at compile time by constructors, to reject unsupported
+    // arities.
+    const ARITY_SUPPORTED: () = assert!(
+        ARITY == 3 || ARITY == 4,
+        "binary fuse filter supports arity 3 and 4"
+    );
+
+    /// Number of seeds tried while building the filter, the last one being
+    /// [BinaryFuse::seed]. Zero for deserialized filters.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// Number of duplicate digests removed while building the filter.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Hash builder used to compute digests of keys.
+    pub fn hash_builder(&self) -> &H {
+        &self.hash_builder
+    }
+
+    /// Seed mixed with digests of keys, to locate their fingerprints.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of fingerprints in each segment.
+    pub fn segment_length(&self) -> u32 {
+        self.segment_length
+    }
+
+    /// Number of segments a key's first fingerprint can fall in.
+    pub fn segment_count(&self) -> u32 {
+        self.segment_count
+    }
+
+    /// Fingerprints of the filter.
+    pub fn finger_prints(&self) -> &[F] {
+        &self.finger_prints
+    }
+
+    fn build_keys_with(
+        &mut self,
+        keys: &[u64],
+        max_attempts: usize,
+        rng_seed: u64,
+    ) -> Result<(), BuildError> {
+        let (mut size, mut rngcounter) = (keys.len(), rng_seed);
+        let (mut keys, mut deduped) = (Cow::Borrowed(keys), false);
+        let mut seeds = vec![];
+        self.attempts = 0;
+        self.duplicates = 0;
+        self.init_layout(size as u32);
+        self.seed = splitmix64(&mut rngcounter);
+
+        let array_length = self.finger_prints.len();
+        let mut block_bits = 1;
+        while (1_u32 << block_bits) < self.segment_count {
+            block_bits += 1;
+        }
+
+        let mut hashes: Vec<u64> = vec![0; size];
+        let mut starts: Vec<usize> = vec![0; (1 << block_bits) + 1];
+        let mut t2count: Vec<u8> = vec![0; array_length];
+        let mut t2hash: Vec<u64> = vec![0; array_length];
+        let mut alone: Vec<u32> = Vec::with_capacity(array_length);
+        let mut stack: Vec<u64> = Vec::with_capacity(size);
+        let mut stack_found: Vec<u8> = Vec::with_capacity(size);
+
+        loop {
+            seeds.push(self.seed);
+            // order the hashes by segment, using counting sort, so that
+            // updates to t2count and t2hash stay close in memory.
+            starts.iter_mut().for_each(|x| *x = 0);
+            for key in keys.iter() {
+                let hash = mixsplit(*key, self.seed);
+                starts[((hash >> (64 - block_bits)) as usize) + 1] += 1;
+            }
+            for i in 1..starts.len() {
+                starts[i] += starts[i - 1];
+            }
+            for key in keys.iter() {
+                let hash = mixsplit(*key, self.seed);
+                let block = (hash >> (64 - block_bits)) as usize;
+                hashes[starts[block]] = hash;
+                starts[block] += 1;
+            }
+
+            let mut overflow = false;
+            for hash in hashes.iter() {
+                let hs = self.get_hashes(*hash);
+                for (i, h) in hs.iter().enumerate() {
+                    let h = *h as usize;
+                    t2count[h] = t2count[h].wrapping_add(4) ^ (i as u8);
+                    t2hash[h] ^= *hash;
+                    // count wrapped around beyond 63 keys.
+                    overflow = overflow || t2count[h] < 4;
+                }
+            }
+
+            stack.clear();
+            stack_found.clear();
+
+            if !overflow {
+                alone.clear();
+                for (i, count) in t2count.iter().enumerate() {
+                    if (count >> 2) == 1 {
+                        alone.push(i as u32);
+                    }
+                }
+
+                while let Some(index) = alone.pop() {
+                    let index = index as usize;
+                    if (t2count[index] >> 2) != 1 {
+                        continue;
+                    }
+                    let hash = t2hash[index];
+                    let found = t2count[index] & 3;
+                    stack.push(hash);
+                    stack_found.push(found);
+
+                    let hs = self.get_hashes(hash);
+                    for (i, other) in hs.iter().enumerate() {
+                        let other = *other as usize;
+                        if i == (found as usize) {
+                            continue;
+                        } else if (t2count[other] >> 2) == 2 {
+                            alone.push(other as u32);
+                        }
+                        t2count[other] = (t2count[other] - 4) ^ (i as u8);
+                        t2hash[other] ^= hash;
+                    }
+                }
+
+                if stack.len() == size {
+                    self.attempts = seeds.len();
+                    break;
+                }
+            }
+
+            if seeds.len() >= max_attempts {
+                let unpeeled = size - stack.len();
+                return Err(BuildError { unpeeled, seeds });
+            }
+
+            // peeling can never succeed with duplicate digests.
+            if !deduped {
+                if let Some(uniq) = dedup_keys(&keys) {
+                    self.duplicates = size - uniq.len();
+                    size = uniq.len();
+                    keys = Cow::Owned(uniq);
+                    hashes.truncate(size);
+                }
+                deduped = true;
+            }
+
+            t2count.iter_mut().for_each(|x| *x = 0);
+            t2hash.iter_mut().for_each(|x| *x = 0);
+            self.seed = splitmix64(&mut rngcounter)
+        }
+
+        let iter = stack.iter().zip(stack_found.iter()).rev();
+        for (hash, found) in iter {
+            let found = *found as usize;
+            let hs = self.get_hashes(*hash);
+            let mut val = F::from_hash(*hash);
+            for (i, h) in hs.iter().enumerate() {
+                if i != found {
+                    val ^= self.finger_prints[*h as usize];
+                }
+            }
+            self.finger_prints[hs[found] as usize] = val;
+        }
+
+        Ok(())
+    }
+
+    /// Contains tell you whether the key is likely part of the set.
+    pub fn contains<T: ?Sized + Hash>(&self, key: &T) -> bool {
+        let hashed_key = self.hash_builder.hash_one(key);
+        self.contains_key(hashed_key)
+    }
+
+    pub fn contains_key(&self, key: u64) -> bool {
+        let hash = mixsplit(key, self.seed);
+        let f = F::from_hash(hash);
+        let hs = self.get_hashes(hash);
+        f == hs
+            .iter()
+            .fold(F::default(), |acc, h| acc ^ self.finger_prints[*h as usize])
+    }
+
+    // Size the segments and the fingerprint array for `size` keys, as
+    // per the reference implementation.
+    fn init_layout(&mut self, size: u32) {
+        // ARITY is 3 or 4, refer to ARITY_SUPPORTED.
+        let (base, offset, min_factor, factor) = match ARITY {
+            3 => (3.33_f64, 2.25, 1.125, (0.875, 0.25, 1_000_000_f64)),
+            _ => (2.91_f64, -0.5, 1.075, (0.77, 0.305, 600_000_f64)),
+        };
+        let segment_length = match size {
+            0 => 4,
+            size => {
+                let n = ((size as f64).ln() / base.ln() + offset).floor().max(2.0);
+                std::cmp::min(1_u32 << (n as u32), MAX_SEGMENT_LENGTH)
+            }
+        };
+        let capacity = match size {
+            0 | 1 => 0,
+            size => {
+                let (a, b, n) = factor;
+                let factor = a + b * n.ln() / (size as f64).ln();
+                ((size as f64) * factor.max(min_factor)).round() as u32
+            }
+        };
+        let arity = ARITY as u32;
+        let segment_count = match capacity.div_ceil(segment_length) {
+            n if n < arity => 1,
+            n => n - (arity - 1),
+        };
+
+        self.segment_length = segment_length;
+        self.segment_length_mask = segment_length - 1;
+        self.segment_count = segment_count;
+        self.segment_count_length = segment_count * segment_length;
+        let array_length = (segment_count + arity - 1) * segment_length;
+        self.finger_prints = vec![Default::default(); array_length as usize];
+    }
+
+    // Position of key, with `hash`, in each of its `ARITY` consecutive
+    // segments. Position in the first segment is picked by the high-bits
+    // of hash, subsequent positions are xor-ed with 18-bit slices of
+    // hash's low-bits.
+    #[inline]
+    fn get_hashes(&self, hash: u64) -> [u32; ARITY] {
+        let h0 = mulhi(hash, self.segment_count_length as u64) as u32;
+        let mut hs = [h0; ARITY];
+        for (i, h) in hs.iter_mut().enumerate().skip(1) {
+            let shift = 18 * (ARITY - 1 - i);
+            *h += (i as u32) * self.segment_length;
+            *h ^= (hash >> shift) as u32 & self.segment_length_mask;
+        }
+        hs
+    }
+}
+
+impl<F, H, const ARITY: usize> BinaryFuse<F, H, ARITY>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// File signature write on first 4 bytes of file.
+    /// ^ stands for xor
+    /// F stands for 3-wise binary fuse, Q for 4-wise binary fuse
+    /// L stands for 1-byte fingerprints, 2 for 2-byte fingerprints
+    /// 1 stands for version 1
+    const SIGNATURE_V1: [u8; 4] = {
+        let arity = if ARITY == 3 { b'F' } else { b'Q' };
+        match F::SIZE {
+            1 => [b'^', arity, b'L', 1],
+            2 => [b'^', arity, b'2', 1],
+            _ => [b'^', arity, b'4', 1],
+        }
+    };
+
+    /// METADATA_LENGTH is size that required to write size of all the
+    /// metadata of the serialized filter.
+    // signature length + seed length + segment-length + segment-count +
+    //      fingerprint count, followed by hasher's identity and parameters.
+    const METADATA_LENGTH: usize = 4 + 8 + 4 + 4 + 4;
+
+    /// Write to file in binary format
+    pub fn write_file(&self, path: &ffi::OsStr) -> Result<usize, Error>
+    where
+        H: SerializableHasher,
+    {
+        let mut f = fs::File::create(path)?;
+        let buf = self.to_bytes();
+        f.write_all(&buf)?;
+        Ok(buf.len())
+    }
+
+    /// Read from file in binary format
+    pub fn read_file(path: &ffi::OsStr) -> Result<Self, Error>
+    where
+        H: SerializableHasher,
+    {
+        let mut f = fs::File::open(path)?;
+        let mut data = Vec::new();
+        f.read_to_end(&mut data)?;
+        Self::from_bytes(data)
+    }
+
+    /// Fingerprints are encoded in big-endian, the fingerprint-count in
+    /// the header is the number of fingerprints. Header is followed by the
+    /// identity and parameters of the hash builder.
+    pub fn to_bytes(&self) -> Vec<u8>
+    where
+        H: SerializableHasher,
+    {
+        let capacity = Self::METADATA_LENGTH + (self.finger_prints.len() * F::SIZE);
+        let mut buf: Vec<u8> = Vec::with_capacity(capacity);
+        buf.extend_from_slice(&Self::SIGNATURE_V1);
+        buf.extend_from_slice(&self.seed.to_be_bytes());
+        buf.extend_from_slice(&self.segment_length.to_be_bytes());
+        buf.extend_from_slice(&self.segment_count.to_be_bytes());
+        buf.extend_from_slice(&(self.finger_prints.len() as u32).to_be_bytes());
+        write_hasher(&self.hash_builder, &mut buf);
+        F::encode(&self.finger_prints, &mut buf);
+        buf
+    }
+
+    /// Deserialize filter, fails with [Error::HasherMismatch] if filter
+    /// was serialized with another type of hash builder.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self, Error>
+    where
+        H: SerializableHasher,
+    {
+        let () = Self::ARITY_SUPPORTED;
+        // validate the buf first.
+        if buf.len() >= 4 && buf[..3] != Self::SIGNATURE_V1[..3] {
+            return Err(Error::InvalidSignature(buf[..3].try_into().unwrap()));
+        }
+        if buf.len() >= 4 && buf[3] != Self::SIGNATURE_V1[3] {
+            return Err(Error::UnsupportedVersion(buf[3]));
+        }
+        if Self::METADATA_LENGTH > buf.len() {
+            let (expected, actual) = (Self::METADATA_LENGTH, buf.len());
+            return Err(Error::Truncated { expected, actual });
+        }
+        let segment_length = u32::from_be_bytes(buf[12..16].try_into().unwrap());
+        let segment_count = u32::from_be_bytes(buf[16..20].try_into().unwrap());
+        let fp_len = u32::from_be_bytes(buf[20..24].try_into().unwrap()) as usize;
+        if !segment_length.is_power_of_two() || segment_length > MAX_SEGMENT_LENGTH {
+            return Err(Error::InvalidSegmentLength(segment_length));
+        }
+        match segment_count
+            .checked_add(ARITY as u32 - 1)
+            .map(|n| n as u64 * segment_length as u64)
+        {
+            Some(n) if segment_count > 0 && n == (fp_len as u64) => (),
+            _ => {
+                return Err(Error::InvalidSegmentCount {
+                    segment_count,
+                    fp_len,
+                })
+            }
+        }
+        let (id, params, n) = read_hasher(&buf[Self::METADATA_LENGTH..])?;
+        let hash_builder = make_hasher(id, params)?;
+        let offset = Self::METADATA_LENGTH + n;
+
+        let (expected, actual) = (fp_len * F::SIZE, buf.len() - offset);
+        if actual < expected {
+            return Err(Error::Truncated { expected, actual });
+        } else if actual > expected {
+            return Err(Error::TrailingBytes(actual - expected));
+        }
+        Ok(BinaryFuse {
+            attempts: 0,
+            duplicates: 0,
+            hash_builder,
+            seed: u64::from_be_bytes(buf[4..12].try_into().unwrap()),
+            segment_length,
+            segment_length_mask: segment_length - 1,
+            segment_count,
+            segment_count_length: segment_count * segment_length,
+            finger_prints: F::decode(&buf[offset..]),
+        })
+    }
+}
+
+#[inline]
+fn mulhi(a: u64, b: u64) -> u64 {
+    (((a as u128) * (b as u128)) >> 64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splitmix64;
+    use rand::{prelude::random, rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_fuse8_basic1() {
+        let seed: u128 = random();
+        println!("test_fuse8_basic1 seed {}", seed);
+        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+        let testsize = 1_000_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = rng.gen();
+        }
+
+        let filter = {
+            let mut builder = BinaryFuse8Builder::<RandomState>::new();
+            builder.populate(&keys);
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (10_000_000, 0_f64);
+        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
+        println!("test_fuse8_basic1 bits per entry {} bits", bpv);
+        assert!(bpv < 9.3, "bpv({}) >= 9.3", bpv);
+
+        for _ in 0..falsesize {
+            if filter.contains(&rng.gen::<u64>()) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_fuse8_basic1 false positive rate {}%", fpp);
+        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
+    }
+
+    #[test]
+    fn test_fuse8_basic2() {
+        let mut seed: u64 = random();
+        println!("test_fuse8_basic2 seed {}", seed);
+
+        let testsize = 100_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = splitmix64(&mut seed);
+        }
+
+        let filter = {
+            let mut builder = BinaryFuse8Builder::<BuildHasherDefault>::new();
+            keys.iter().for_each(|key| builder.insert(key));
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (1_000_000, 0_f64);
+        for _ in 0..falsesize {
+            let v = splitmix64(&mut seed);
+            if filter.contains(&v) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_fuse8_basic2 false positive rate {}%", fpp);
+        assert!(fpp < 0.45, "fpp({}) >= 0.45", fpp);
+    }
+
+    #[test]
+    fn test_fuse16_basic1() {
+        let mut seed: u64 = random();
+        println!("test_fuse16_basic1 seed {}", seed);
+
+        let testsize = 1_000_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = splitmix64(&mut seed);
+        }
+
+        let filter = {
+            let mut builder = BinaryFuse16Builder::<BuildHasherDefault>::new();
+            builder.populate_keys(&keys);
+            builder.build()
+        };
+
+        for key in keys.into_iter() {
+            assert!(filter.contains_key(key), "key {} not present", key);
+        }
+
+        let bpv = (filter.finger_prints.len() as f64) * 16.0 / (testsize as f64);
+        println!("test_fuse16_basic1 bits per entry {} bits", bpv);
+        assert!(bpv < 18.5, "bpv({}) >= 18.5", bpv);
+
+        let (falsesize, mut matches) = (1_000_000, 0_f64);
+        for _ in 0..falsesize {
+            if filter.contains_key(splitmix64(&mut seed)) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_fuse16_basic1 false positive rate {}%", fpp);
+        assert!(fpp < 0.01, "fpp({}) >= 0.01", fpp);
+    }
+
+    #[test]
+    fn test_fuse4wise8_basic1() {
+        let seed: u128 = random();
+        println!("test_fuse4wise8_basic1 seed {}", seed);
+        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+        let testsize = 1_000_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = rng.gen();
+        }
+
+        let filter = {
+            let mut builder = BinaryFuse4Wise8Builder::<BuildHasherDefault>::new();
+            builder.populate_keys(&keys);
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains_key(*key), "key {} not present", key);
+        }
+
+        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
+        println!("test_fuse4wise8_basic1 bits per entry {} bits", bpv);
+        assert!(bpv < 8.8, "bpv({}) >= 8.8", bpv);
+
+        let (falsesize, mut matches) = (1_000_000, 0_f64);
+        for _ in 0..falsesize {
+            if filter.contains_key(rng.gen::<u64>()) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_fuse4wise8_basic1 false positive rate {}%", fpp);
+        assert!(fpp < 0.45, "fpp({}) >= 0.45", fpp);
+    }
+
+    #[test]
+    fn test_fuse4wise16_basic1() {
+        let mut seed: u64 = random();
+        println!("test_fuse4wise16_basic1 seed {}", seed);
+
+        let keys: Vec<u64> = (0..100_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = {
+            let mut builder = BinaryFuse4Wise16Builder::<BuildHasherDefault>::new();
+            keys.iter().for_each(|key| builder.insert(key));
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (1_000_000, 0_f64);
+        for _ in 0..falsesize {
+            if filter.contains(&splitmix64(&mut seed)) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_fuse4wise16_basic1 false positive rate {}%", fpp);
+        assert!(fpp < 0.01, "fpp({}) >= 0.01", fpp);
+    }
+
+    #[test]
+    fn test_fuse8_small_sets() {
+        let mut seed: u64 = random();
+        println!("test_fuse8_small_sets seed {}", seed);
+
+        for size in [0, 1, 2, 3, 10, 100, 1000].iter() {
+            let keys: Vec<u64> = (0..*size).map(|_| splitmix64(&mut seed)).collect();
+            let filter =
+                BinaryFuse8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+            for key in keys.iter() {
+                assert!(filter.contains_key(*key), "key {} not present", key);
+            }
+
+            let filter =
+                BinaryFuse4Wise8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+            for key in keys.iter() {
+                assert!(filter.contains_key(*key), "key {} not present", key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fuse_try_build() {
+        let mut seed: u64 = random();
+        println!("test_fuse_try_build seed {}", seed);
+
+        let mut keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let mut builder = BinaryFuse8Builder::<BuildHasherDefault>::new();
+        builder.populate_keys(&keys);
+        let filter = builder.try_build().unwrap();
+        for key in keys.iter() {
+            assert!(filter.contains_key(*key), "key {} not present", key);
+        }
+
+        // duplicates are removed only after the first attempt fails.
+        keys.push(keys[100]);
+        let mut builder = BinaryFuse4Wise8Builder::<BuildHasherDefault>::new();
+        builder.set_max_attempts(0);
+        let err = builder.clone().try_build_keys(&keys).err().unwrap();
+        assert_eq!(err.seeds.len(), 1);
+        assert!(err.unpeeled >= 2, "unpeeled {}", err.unpeeled);
+
+        builder.set_max_attempts(3);
+        let filter = builder.try_build_keys(&keys).unwrap();
+        assert_eq!(filter.duplicates(), 1);
+        assert!(filter.attempts() >= 2, "attempts {}", filter.attempts());
+        for key in keys.iter() {
+            assert!(filter.contains_key(*key), "key {} not present", key);
+        }
+    }
+
+    #[test]
+    fn test_fuse_set_seed() {
+        let mut seed: u64 = random();
+        println!("test_fuse_set_seed seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = BinaryFuse8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        let mut rngcounter = DEFAULT_RNG_SEED;
+        let seeds: Vec<u64> = (0..filter.attempts())
+            .map(|_| splitmix64(&mut rngcounter))
+            .collect();
+        assert_eq!(filter.seed(), *seeds.last().unwrap());
+
+        // same keys and rng_seed yield the same filter.
+        let rng_seed = splitmix64(&mut seed);
+        let mut builder = BinaryFuse16Builder::<BuildHasherDefault>::new();
+        builder.set_seed(rng_seed).populate_keys(&keys);
+        let filter1 = builder.clone().build();
+        let filter2 = builder.build();
+        assert!(
+            filter1 == filter2,
+            "filters differ for rng_seed {}",
+            rng_seed
+        );
+        assert_eq!(filter1.attempts(), filter2.attempts());
+
+        let mut rngcounter = rng_seed;
+        let seeds: Vec<u64> = (0..filter1.attempts())
+            .map(|_| splitmix64(&mut rngcounter))
+            .collect();
+        assert_eq!(filter1.seed(), *seeds.last().unwrap());
+        for key in keys.iter() {
+            assert!(filter1.contains_key(*key), "key {} not present", key);
+        }
+
+        // failed builds report seeds drawn from rng_seed.
+        let mut builder = BinaryFuse8Builder::<BuildHasherDefault>::new();
+        builder.set_seed(rng_seed).set_max_attempts(0);
+        let err = builder.try_build_keys(&[keys[0], keys[0]]).err().unwrap();
+        let mut rngcounter = rng_seed;
+        assert_eq!(err.seeds, vec![splitmix64(&mut rngcounter)]);
+
+        let filter = BinaryFuse8::<BuildHasherDefault>::from_bytes(filter.to_bytes());
+        assert_eq!(filter.unwrap().attempts(), 0);
+    }
+
+    #[test]
+    fn test_fuse_duplicate_keys() {
+        let mut seed: u64 = random();
+        println!("test_fuse_duplicate_keys seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let mut builder = BinaryFuse16Builder::<BuildHasherDefault>::new();
+        builder.populate_keys(&keys);
+        builder.populate_keys(&keys[..5000]);
+        builder.populate_keys(&keys[..100]);
+        let filter = builder.build();
+        assert_eq!(filter.duplicates(), 5100);
+        for key in keys.iter() {
+            assert!(filter.contains_key(*key), "key {} not present", key);
+        }
+
+        let filter = BinaryFuse16Builder::<BuildHasherDefault>::new()
+            .build_keys(&[keys[0], keys[0], keys[0]]);
+        assert_eq!(filter.duplicates(), 2);
+        assert!(filter.contains_key(keys[0]));
+    }
+
+    #[test]
+    fn test_fuse_bytes_encoding() {
+        let mut seed: u64 = random();
+        println!("test_fuse_bytes_encoding seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let mut builder = BinaryFuse16Builder::<BuildHasherDefault>::new();
+        builder.populate_keys(&keys);
+        let filter = builder.build();
+
+        let buf = filter.to_bytes();
+        assert!(BinaryFuse8::<BuildHasherDefault>::from_bytes(buf.clone()).is_err());
+        assert!(crate::Xor16::<BuildHasherDefault>::from_bytes(buf.clone()).is_err());
+        assert!(
+            BinaryFuse4Wise16::<BuildHasherDefault>::from_bytes(buf.clone()).is_err()
+        );
+
+        let mut short = buf.clone();
+        short.pop();
+        let err = BinaryFuse16::<BuildHasherDefault>::from_bytes(short).err();
+        assert!(matches!(err, Some(Error::Truncated { .. })), "{:?}", err);
+
+        let mut long = buf.clone();
+        long.push(0);
+        let err = BinaryFuse16::<BuildHasherDefault>::from_bytes(long).err();
+        assert!(matches!(err, Some(Error::TrailingBytes(1))), "{:?}", err);
+
+        let mut bad = buf.clone();
+        bad[12..16].copy_from_slice(&3_u32.to_be_bytes());
+        let err = BinaryFuse16::<BuildHasherDefault>::from_bytes(bad).err();
+        assert!(
+            matches!(err, Some(Error::InvalidSegmentLength(3))),
+            "{:?}",
+            err
+        );
+
+        let filter_read = BinaryFuse16::<BuildHasherDefault>::from_bytes(buf).unwrap();
+        assert!(
+            filter_read == filter,
+            "Filter unequals after encode and decode"
+        );
+        for key in keys.iter() {
+            assert!(filter_read.contains_key(*key), "key {} not present", key);
+        }
+
+        let hash_builder = crate::BuildSipHasher13::new(seed, !seed);
+        let mut builder = BinaryFuse8Builder::with_hasher(hash_builder);
+        builder.populate(&keys);
+        let filter = builder.build();
+        let buf = filter.to_bytes();
+        let err = BinaryFuse8::<BuildHasherDefault>::from_bytes(buf.clone()).err();
+        assert!(
+            matches!(err, Some(Error::HasherMismatch { .. })),
+            "{:?}",
+            err
+        );
+        let filter_read =
+            BinaryFuse8::<crate::BuildSipHasher13>::from_bytes(buf).unwrap();
+        for key in keys.iter() {
+            assert!(filter_read.contains(key), "key {} not present", key);
+        }
+    }
+}
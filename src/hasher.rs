@@ -0,0 +1,395 @@
+use std::{
+    convert::TryInto,
+    hash::{BuildHasher, Hasher},
+};
+
+use crate::{BuildHasherDefault, Error};
+
+/// Hash builders that can be persisted along with the filter, so that a
+/// deserialized filter hashes keys exactly like the filter that was
+/// serialized.
+///
+/// Serialized filters carry the builder's [SerializableHasher::ID] and
+/// parameters, deserializing them with a different hash builder type
+/// fails with [Error::HasherMismatch].
+pub trait SerializableHasher: BuildHasher + Sized {
+    /// Unique identifier of the hash function, saved in serialized filters.
+    const ID: [u8; 4];
+
+    /// Encode parameters of this hash builder, like its keys.
+    fn to_params(&self) -> Vec<u8>;
+
+    /// Create hash builder from parameters encoded by
+    /// [SerializableHasher::to_params].
+    fn from_params(params: &[u8]) -> Result<Self, Error>;
+}
+
+impl SerializableHasher for BuildHasherDefault {
+    const ID: [u8; 4] = *b"SD13";
+
+    fn to_params(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn from_params(params: &[u8]) -> Result<Self, Error> {
+        match params.len() {
+            0 => Ok(BuildHasherDefault::default()),
+            _ => Err(Error::InvalidHasherParams(Self::ID)),
+        }
+    }
+}
+
+/// Hash builder for [SipHasher13] with caller supplied keys.
+///
+/// Useful when keys are untrusted and filters must not be predictable,
+/// unlike [RandomState](std::collections::hash_map::RandomState) the keys
+/// are persisted along with the filter.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BuildSipHasher13 {
+    k0: u64,
+    k1: u64,
+}
+
+impl BuildSipHasher13 {
+    /// Create a hash builder with keys `k0` and `k1`.
+    pub fn new(k0: u64, k1: u64) -> BuildSipHasher13 {
+        BuildSipHasher13 { k0, k1 }
+    }
+}
+
+impl BuildHasher for BuildSipHasher13 {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+impl SerializableHasher for BuildSipHasher13 {
+    const ID: [u8; 4] = *b"SK13";
+
+    fn to_params(&self) -> Vec<u8> {
+        let mut params = self.k0.to_be_bytes().to_vec();
+        params.extend_from_slice(&self.k1.to_be_bytes());
+        params
+    }
+
+    fn from_params(params: &[u8]) -> Result<Self, Error> {
+        match params.len() {
+            16 => Ok(BuildSipHasher13 {
+                k0: u64::from_be_bytes(params[..8].try_into().unwrap()),
+                k1: u64::from_be_bytes(params[8..].try_into().unwrap()),
+            }),
+            _ => Err(Error::InvalidHasherParams(Self::ID)),
+        }
+    }
+}
+
+// Append identity and parameters of `hash_builder` into `buf`.
+pub(crate) fn write_hasher<H>(hash_builder: &H, buf: &mut Vec<u8>)
+where
+    H: SerializableHasher,
+{
+    let params = hash_builder.to_params();
+    buf.extend_from_slice(&H::ID);
+    buf.extend_from_slice(&(params.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&params);
+}
+
+// Read identity and parameters, written by `write_hasher`, from `buf`.
+// Return the identity, the parameters and the number of bytes read.
+pub(crate) fn read_hasher(buf: &[u8]) -> Result<([u8; 4], &[u8], usize), Error> {
+    if buf.len() < 8 {
+        return Err(Error::Truncated {
+            expected: 8,
+            actual: buf.len(),
+        });
+    }
+    let id: [u8; 4] = buf[..4].try_into().unwrap();
+    let n = u32::from_be_bytes(buf[4..8].try_into().unwrap()) as usize;
+    if (buf.len() - 8) < n {
+        let (expected, actual) = (n, buf.len() - 8);
+        return Err(Error::Truncated { expected, actual });
+    }
+    Ok((id, &buf[8..(8 + n)], 8 + n))
+}
+
+// Create hash builder from identity and parameters read from serialized
+// filter, fail if `id` is not of `H`.
+pub(crate) fn make_hasher<H>(id: [u8; 4], params: &[u8]) -> Result<H, Error>
+where
+    H: SerializableHasher,
+{
+    match id == H::ID {
+        true => H::from_params(params),
+        false => Err(Error::HasherMismatch {
+            expected: H::ID,
+            actual: id,
+        }),
+    }
+}
+
+/// Type SipHasher13 implements SipHash-1-3, the algorithm behind
+/// [std::collections::hash_map::DefaultHasher] as of this writing, frozen
+/// inside this crate.
+///
+/// Unlike DefaultHasher, its output is guaranteed to stay the same across
+/// Rust releases and platforms, making it suitable for filters persisted
+/// to disk. Integers are hashed in little-endian byte order and `usize`
+/// and `isize` are widened to 64 bits. With zero keys, output matches
+/// DefaultHasher on 64-bit little-endian platforms.
+///
+/// Note that only the hash function is frozen here, the bytes fed to it
+/// come from the [Hash](std::hash::Hash) implementation of the key type.
+#[derive(Clone, Debug)]
+pub struct SipHasher13 {
+    k0: u64,
+    k1: u64,
+    length: usize, // bytes processed so far
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    tail: u64,    // unprocessed bytes, little-endian
+    ntail: usize, // number of valid bytes in tail
+}
+
+impl Default for SipHasher13 {
+    fn default() -> SipHasher13 {
+        SipHasher13::new_with_keys(0, 0)
+    }
+}
+
+impl SipHasher13 {
+    /// Create a new hasher keyed by `k0` and `k1`.
+    pub fn new_with_keys(k0: u64, k1: u64) -> SipHasher13 {
+        SipHasher13 {
+            k0,
+            k1,
+            length: 0,
+            v0: k0 ^ 0x736f_6d65_7073_6575,
+            v1: k1 ^ 0x646f_7261_6e64_6f6d,
+            v2: k0 ^ 0x6c79_6765_6e65_7261,
+            v3: k1 ^ 0x7465_6462_7974_6573,
+            tail: 0,
+            ntail: 0,
+        }
+    }
+
+    /// Return the keys of this hasher.
+    pub fn keys(&self) -> (u64, u64) {
+        (self.k0, self.k1)
+    }
+
+    #[inline]
+    fn sip_round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13);
+        self.v1 ^= self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16);
+        self.v3 ^= self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21);
+        self.v3 ^= self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17);
+        self.v1 ^= self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    #[inline]
+    fn compress(&mut self, m: u64) {
+        self.v3 ^= m;
+        self.sip_round();
+        self.v0 ^= m;
+    }
+}
+
+// load upto 8 bytes from `buf` as little-endian integer.
+#[inline]
+fn load_le(buf: &[u8]) -> u64 {
+    buf.iter().rev().fold(0, |acc, b| (acc << 8) | (*b as u64))
+}
+
+impl Hasher for SipHasher13 {
+    fn write(&mut self, msg: &[u8]) {
+        self.length += msg.len();
+
+        let mut msg = msg;
+        if self.ntail != 0 {
+            let needed = 8 - self.ntail;
+            let n = needed.min(msg.len());
+            self.tail |= load_le(&msg[..n]) << (8 * self.ntail);
+            if msg.len() < needed {
+                self.ntail += msg.len();
+                return;
+            }
+            let m = self.tail;
+            self.compress(m);
+            self.tail = 0;
+            self.ntail = 0;
+            msg = &msg[needed..];
+        }
+
+        let mut chunks = msg.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.compress(load_le(chunk));
+        }
+        let rem = chunks.remainder();
+        self.tail = load_le(rem);
+        self.ntail = rem.len();
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.write(&[i])
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i8(&mut self, i: i8) {
+        self.write_u8(i as u8)
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16)
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32)
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128)
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as i64 as u64)
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+
+        let b: u64 = (((self.length as u64) & 0xff) << 56) | self.tail;
+        state.compress(b);
+        state.v2 ^= 0xff;
+        state.sip_round();
+        state.sip_round();
+        state.sip_round();
+
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::random;
+    use std::hash::{BuildHasher, Hash};
+
+    fn hash<T: ?Sized + Hash>(key: &T) -> u64 {
+        let mut hasher = SipHasher13::default();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_siphasher13_frozen() {
+        // these values must never change, filters persisted to disk
+        // depend on them.
+        assert_eq!(hash(&0_u64), 0xbd60_acb6_58c7_9e45);
+        assert_eq!(hash(&u64::MAX), 0x2f20_5be2_fec8_e38d);
+        assert_eq!(hash("hello world"), 0x7161_eb55_98be_c7cf);
+        assert_eq!(
+            hash(&[1_u8, 2, 3, 4, 5, 6, 7, 8, 9][..]),
+            0xd1e9_bd33_cee6_85bd
+        );
+
+        let mut hasher =
+            SipHasher13::new_with_keys(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+        hasher.write(&(0..15).collect::<Vec<u8>>());
+        assert_eq!(hasher.finish(), 0xd320_d86d_2a51_9956);
+    }
+
+    #[test]
+    fn test_serializable_hasher() {
+        let hash_builder = BuildSipHasher13::new(random(), random());
+        let params = hash_builder.to_params();
+        assert_eq!(params.len(), 16);
+        assert_eq!(
+            BuildSipHasher13::from_params(&params).unwrap(),
+            hash_builder
+        );
+        assert!(BuildSipHasher13::from_params(&params[1..]).is_err());
+        assert!(BuildHasherDefault::from_params(&params).is_err());
+
+        let mut buf = vec![];
+        write_hasher(&hash_builder, &mut buf);
+        buf.push(0xAB);
+        let (id, params, n) = read_hasher(&buf).unwrap();
+        assert_eq!(n, 24);
+        assert!(make_hasher::<BuildSipHasher13>(id, params).unwrap() == hash_builder);
+        match make_hasher::<BuildHasherDefault>(id, params) {
+            Err(Error::HasherMismatch { expected, actual }) => {
+                assert_eq!(&expected, b"SD13");
+                assert_eq!(&actual, b"SK13");
+            }
+            _ => panic!("expected hasher mismatch"),
+        }
+        assert!(read_hasher(&buf[..20]).is_err());
+    }
+
+    #[cfg(all(target_endian = "little", target_pointer_width = "64"))]
+    #[test]
+    fn test_siphasher13_default_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let std_hasher = BuildHasherDefault::<DefaultHasher>::default();
+        let seed: u64 = random();
+        println!("test_siphasher13_default_hasher seed {}", seed);
+
+        for i in 0..1000_u64 {
+            let key = seed.wrapping_add(i);
+            assert_eq!(hash(&key), std_hasher.hash_one(key));
+            assert_eq!(hash(&(key as u32)), std_hasher.hash_one(key as u32));
+            let s = key.to_string().repeat((i % 7) as usize);
+            assert_eq!(hash(&s), std_hasher.hash_one(&s));
+            let v: Vec<u16> = (0..(i % 13)).map(|x| (key + x) as u16).collect();
+            assert_eq!(hash(&v), std_hasher.hash_one(&v));
+        }
+
+        // split writes across the 8-byte boundary.
+        let mut hasher = SipHasher13::default();
+        let mut std_hasher = DefaultHasher::new();
+        for n in 0..20 {
+            let buf: Vec<u8> = (0..n).collect();
+            hasher.write(&buf);
+            std_hasher.write(&buf);
+            assert_eq!(hasher.finish(), std_hasher.finish());
+        }
+    }
+}
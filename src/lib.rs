@@ -3,15 +3,59 @@
 //! This is a port of its
 //! [original implementation](https://github.com/FastFilter/xorfilter)
 //! written in golang.
+//!
+//! Filter is implemented by [XorFilter] type, parametrized over its
+//! [Fingerprint] width. [Xor8], [Xor16] and [Xor32] are type aliases for
+//! 8-bit, 16-bit and 32-bit fingerprints. Wider fingerprints take more
+//! memory for a much lower false positive rate.
+//!
+//! [BinaryFuse] type implements the 3-wise binary fuse filter, with
+//! [BinaryFuse8] and [BinaryFuse16] type aliases. It is built faster and
+//! takes about 10% less memory than [XorFilter] of same fingerprint width.
+//! [BinaryFuse4Wise8] and [BinaryFuse4Wise16] use 4-wise construction to
+//! save another 5% of memory, for slightly slower lookups.
+//!
+//! [XorLsm] accepts new keys after it is built, by buffering them and
+//! merging them into levels of [XorFilter], log-structured style.
+//! [XorTombstone] supports removing keys, by pairing [XorFilter] with a
+//! filter of removed keys.
 
-#[allow(unused_imports)]
-use std::collections::hash_map::RandomState;
 use std::{
-    collections::hash_map::DefaultHasher,
     convert::TryInto,
-    ffi, fs,
-    hash::{self, BuildHasher, Hash, Hasher},
-    io::{self, Error, ErrorKind, Read, Write},
+    hash::{self, BuildHasher},
+    ops::{BitXor, BitXorAssign},
+};
+
+#[cfg(feature = "cbor")]
+mod cbor;
+mod error;
+mod fuse;
+mod golang;
+mod hasher;
+mod lsm;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod simd;
+mod tombstone;
+mod xor;
+
+#[cfg(feature = "mmap")]
+pub use mmap::{Xor16Mmap, Xor32Mmap, Xor8Mmap, XorFilterMmap};
+
+pub use error::{BuildError, Error};
+pub use fuse::{
+    BinaryFuse, BinaryFuse16, BinaryFuse16Builder, BinaryFuse4Wise16,
+    BinaryFuse4Wise16Builder, BinaryFuse4Wise8, BinaryFuse4Wise8Builder, BinaryFuse8,
+    BinaryFuse8Builder, BinaryFuseBuilder,
+};
+pub use hasher::{BuildSipHasher13, SerializableHasher, SipHasher13};
+pub use lsm::{Xor16Lsm, Xor32Lsm, Xor8Lsm, XorLsm};
+pub use tombstone::{Xor16Tombstone, Xor32Tombstone, Xor8Tombstone, XorTombstone};
+pub use xor::{
+    Xor16, Xor16Builder, Xor16Ref, Xor32, Xor32Builder, Xor32Ref, Xor8, Xor8Builder,
+    Xor8Ref, XorFilter, XorFilterBuilder, XorFilterRef,
 };
 
 fn murmur64(mut h: u64) -> u64 {
@@ -45,722 +89,215 @@ fn fingerprint(hash: u64) -> u64 {
     hash ^ (hash >> 32)
 }
 
-#[derive(Clone, Default)]
-struct XorSet {
-    xor_mask: u64,
-    count: u32,
-}
-
-#[derive(Default)]
-struct Hashes {
-    h: u64,
-    h0: u32,
-    h1: u32,
-    h2: u32,
-}
-
-#[derive(Clone, Copy, Default)]
-struct KeyIndex {
-    hash: u64,
-    index: u32,
-}
-
-/// Wrapper type for [std::hash::BuildHasherDefault].
-#[derive(Clone)]
-pub struct BuildHasherDefault {
-    hasher: hash::BuildHasherDefault<DefaultHasher>,
-}
+// hint the CPU to fetch the cache line holding `item`, ahead of its use.
+#[inline(always)]
+fn prefetch<T>(item: &T) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::{_mm_prefetch, _MM_HINT_T0};
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
 
-impl From<BuildHasherDefault> for Vec<u8> {
-    fn from(_: BuildHasherDefault) -> Vec<u8> {
-        vec![]
+        // prefetch is only a hint, it does not fault on any address.
+        unsafe { _mm_prefetch(item as *const T as *const i8, _MM_HINT_T0) }
     }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    let _ = item;
 }
 
-impl From<Vec<u8>> for BuildHasherDefault {
-    fn from(_: Vec<u8>) -> BuildHasherDefault {
-        BuildHasherDefault {
-            hasher: hash::BuildHasherDefault::<DefaultHasher>::default(),
+// CRC-32 (IEEE 802.3) lookup table, for reflected polynomial 0xEDB88320.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0_u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            j += 1;
         }
+        table[i] = crc;
+        i += 1;
     }
-}
-
-impl BuildHasher for BuildHasherDefault {
-    type Hasher = DefaultHasher;
+    table
+};
 
-    fn build_hasher(&self) -> Self::Hasher {
-        self.hasher.build_hasher()
-    }
+// returns CRC-32 of `buf` continuing from `crc`, start with 0.
+fn crc32(crc: u32, buf: &[u8]) -> u32 {
+    let crc = buf.iter().fold(!crc, |crc, b| {
+        CRC32_TABLE[((crc ^ (*b as u32)) & 0xFF) as usize] ^ (crc >> 8)
+    });
+    !crc
 }
 
-impl Default for BuildHasherDefault {
-    fn default() -> Self {
-        BuildHasherDefault {
-            hasher: hash::BuildHasherDefault::<DefaultHasher>::default(),
-        }
+// returns sorted and de-duplicated copy of keys, if there are duplicates.
+fn dedup_keys(keys: &[u64]) -> Option<Vec<u64>> {
+    let mut uniq = keys.to_vec();
+    uniq.sort_unstable();
+    uniq.dedup();
+    if uniq.len() < keys.len() {
+        Some(uniq)
+    } else {
+        None
     }
 }
 
-/// Type Xor8 is probabilistic data-structure to test membership of an
-/// element in a set.
-///
-/// This implementation has a false positive rate of about 0.3%
-/// and a memory usage of less than 9 bits per entry for sizeable sets.
-/// Xor8 is parametrized over type `H` which is expected to implement
-/// [BuildHasher] trait, like [RandomState] and [BuildHasherDefault].
-/// When not supplied, `BuildHasherDefault` is used as the default
-/// hash-builder. When applications want to serialize and de-serialize
-/// Xor8, avoid using `RandomState`.
-pub struct Xor8<H = BuildHasherDefault>
-where
-    H: BuildHasher,
-{
-    keys: Option<Vec<u64>>,
-    pub hash_builder: H,
-    pub seed: u64,
-    pub block_length: u32,
-    pub finger_prints: Vec<u8>,
-}
+/// Default number of seeds tried by `try_build` and `try_build_keys`
+/// before giving up on constructing a filter.
+pub const DEFAULT_MAX_ATTEMPTS: usize = 100;
 
-impl<H> PartialEq for Xor8<H>
-where
-    H: BuildHasher,
-{
-    fn eq(&self, other: &Self) -> bool {
-        self.seed == other.seed
-            && self.block_length == other.block_length
-            && self.finger_prints == other.finger_prints
-    }
-}
+/// Default initial state of the random number generator that seeds are
+/// drawn from, refer to [XorFilterBuilder::set_seed] and
+/// [BinaryFuseBuilder::set_seed].
+pub const DEFAULT_RNG_SEED: u64 = 1;
 
-impl<H> Default for Xor8<H>
-where
-    H: BuildHasher + Default,
-{
-    fn default() -> Self {
-        Xor8 {
-            keys: Some(Vec::default()),
-            hash_builder: H::default(),
-            seed: u64::default(),
-            block_length: u32::default(),
-            finger_prints: Vec::default(),
-        }
-    }
-}
-
-impl<H> Xor8<H>
-where
-    H: Default + BuildHasher,
-{
-    /// New Xor8 instance initialized with `DefaultHasher`.
-    pub fn new() -> Self {
-        Default::default()
-    }
-}
-
-impl<H> Xor8<H>
-where
-    H: BuildHasher,
+/// Fingerprint type stored in the filter's array, implemented for [u8],
+/// [u16] and [u32].
+///
+/// This trait is sealed, serialized filters are signed with the fingerprint
+/// width, and only these widths have a signature.
+///
+/// ```compile_fail,E0277
+/// use std::ops::{BitXor, BitXorAssign};
+///
+/// #[derive(Clone, Copy, Default, PartialEq)]
+/// struct Fp64(u64);
+///
+/// impl BitXor for Fp64 {
+///     type Output = Fp64;
+///     fn bitxor(self, other: Fp64) -> Fp64 { Fp64(self.0 ^ other.0) }
+/// }
+///
+/// impl BitXorAssign for Fp64 {
+///     fn bitxor_assign(&mut self, other: Fp64) { self.0 ^= other.0 }
+/// }
+///
+/// impl xorfilter::Fingerprint for Fp64 {
+///     const SIZE: usize = 8;
+///     fn from_hash(hash: u64) -> Fp64 { Fp64(hash) }
+///     fn encode(fps: &[Fp64], buf: &mut Vec<u8>) {}
+///     fn decode(buf: &[u8]) -> Vec<Fp64> { vec![] }
+///     fn decode_at(buf: &[u8], index: usize) -> Fp64 { Fp64(0) }
+/// }
+/// ```
+pub trait Fingerprint:
+    sealed::Sealed + Copy + Default + PartialEq + BitXor<Output = Self> + BitXorAssign
 {
-    /// New Xor8 instance initialized with supplied `hasher`.
-    pub fn with_hasher(hash_builder: H) -> Self {
-        Xor8 {
-            keys: Some(Default::default()),
-            hash_builder,
-            seed: Default::default(),
-            block_length: Default::default(),
-            finger_prints: Default::default(),
-        }
-    }
+    /// Size of fingerprint in bytes.
+    const SIZE: usize;
 
-    /// Insert 64-bit digest of a single key. Digest for the key shall
-    /// be generated using the default-hasher or via hasher supplied via
-    /// [Xor8::with_hasher] method.
-    pub fn insert<T: ?Sized + Hash>(&mut self, key: &T) {
-        let hashed_key = {
-            let mut hasher = self.hash_builder.build_hasher();
-            key.hash(&mut hasher);
-            hasher.finish()
-        };
-        self.keys.as_mut().unwrap().push(hashed_key);
-    }
+    /// Derive fingerprint from the 64-bit hash of a key.
+    fn from_hash(hash: u64) -> Self;
 
-    /// Populate 64-bit digests for collection of keys. Digest for the key
-    /// shall be generated using the default-hasher or via hasher supplied
-    /// via [Xor8::with_hasher] method.
-    pub fn populate<T: Hash>(&mut self, keys: &[T]) {
-        keys.iter().for_each(|key| {
-            let mut hasher = self.hash_builder.build_hasher();
-            key.hash(&mut hasher);
-            self.keys.as_mut().unwrap().push(hasher.finish());
-        })
-    }
+    /// Append big-endian encoding of fingerprints into `buf`.
+    fn encode(fps: &[Self], buf: &mut Vec<u8>);
 
-    /// Populate pre-compute 64-bit digests for keys.
-    pub fn populate_keys(&mut self, keys: &[u64]) {
-        self.keys.as_mut().unwrap().extend_from_slice(keys)
-    }
-
-    /// Build bitmap for keys that are insert using [Xor8::insert] or
-    /// [Xor8::populate] method.
-    pub fn build(&mut self) {
-        let keys = self.keys.take().unwrap();
-        self.build_keys(&keys);
-    }
+    /// Decode fingerprints from big-endian encoded `buf`, trailing
+    /// bytes short of a fingerprint are ignored.
+    fn decode(buf: &[u8]) -> Vec<Self>;
 
-    /// Build a bitmap for pre-computed 64-bit digests for keys. If any
-    /// keys where inserted using [Xor8::insert], [Xor8::populate],
-    /// [Xor8::populate_keys] method shall be ignored.
-    pub fn build_keys(&mut self, keys: &[u64]) {
-        let (size, mut rngcounter) = (keys.len(), 1_u64);
-        let capacity = {
-            let capacity = 32 + ((1.23 * (size as f64)).ceil() as u32);
-            capacity / 3 * 3 // round it down to a multiple of 3
-        };
-        self.seed = splitmix64(&mut rngcounter);
-        self.block_length = capacity / 3;
-        self.finger_prints = vec![Default::default(); capacity as usize];
-
-        let block_length = self.block_length as usize;
-        let mut q0: Vec<KeyIndex> = Vec::with_capacity(block_length);
-        let mut q1: Vec<KeyIndex> = Vec::with_capacity(block_length);
-        let mut q2: Vec<KeyIndex> = Vec::with_capacity(block_length);
-        let mut stack: Vec<KeyIndex> = Vec::with_capacity(size);
-        let mut sets0: Vec<XorSet> = vec![Default::default(); block_length];
-        let mut sets1: Vec<XorSet> = vec![Default::default(); block_length];
-        let mut sets2: Vec<XorSet> = vec![Default::default(); block_length];
-
-        loop {
-            for key in keys.iter() {
-                let hs = self.geth0h1h2(*key);
-                sets0[hs.h0 as usize].xor_mask ^= hs.h;
-                sets0[hs.h0 as usize].count += 1;
-                sets1[hs.h1 as usize].xor_mask ^= hs.h;
-                sets1[hs.h1 as usize].count += 1;
-                sets2[hs.h2 as usize].xor_mask ^= hs.h;
-                sets2[hs.h2 as usize].count += 1;
-            }
-
-            q0.clear();
-            q1.clear();
-            q2.clear();
-
-            let iter = sets0.iter().enumerate().take(self.block_length as usize);
-            for (i, item) in iter {
-                if item.count == 1 {
-                    q0.push(KeyIndex {
-                        index: i as u32,
-                        hash: item.xor_mask,
-                    });
-                }
-            }
-            let iter = sets1.iter().enumerate().take(self.block_length as usize);
-            for (i, item) in iter {
-                if item.count == 1 {
-                    q1.push(KeyIndex {
-                        index: i as u32,
-                        hash: item.xor_mask,
-                    });
-                }
-            }
-            let iter = sets2.iter().enumerate().take(self.block_length as usize);
-            for (i, item) in iter {
-                if item.count == 1 {
-                    q2.push(KeyIndex {
-                        index: i as u32,
-                        hash: item.xor_mask,
-                    });
-                }
-            }
-
-            stack.clear();
-
-            while !q0.is_empty() || !q1.is_empty() || !q2.is_empty() {
-                while let Some(keyindexvar) = q0.pop() {
-                    if sets0[keyindexvar.index as usize].count == 0 {
-                        // not actually possible after the initial scan.
-                        continue;
-                    }
-                    let hash = keyindexvar.hash;
-                    let h1 = self.geth1(hash);
-                    let h2 = self.geth2(hash);
-                    stack.push(keyindexvar);
-
-                    let mut s = unsafe { sets1.get_unchecked_mut(h1 as usize) };
-                    s.xor_mask ^= hash;
-                    s.count -= 1;
-                    if s.count == 1 {
-                        q1.push(KeyIndex {
-                            index: h1,
-                            hash: s.xor_mask,
-                        })
-                    }
-
-                    let mut s = unsafe { sets2.get_unchecked_mut(h2 as usize) };
-                    s.xor_mask ^= hash;
-                    s.count -= 1;
-                    if s.count == 1 {
-                        q2.push(KeyIndex {
-                            index: h2,
-                            hash: s.xor_mask,
-                        })
-                    }
-                }
-                while let Some(mut keyindexvar) = q1.pop() {
-                    if sets1[keyindexvar.index as usize].count == 0 {
-                        continue;
-                    }
-                    let hash = keyindexvar.hash;
-                    let h0 = self.geth0(hash);
-                    let h2 = self.geth2(hash);
-                    keyindexvar.index += self.block_length;
-                    stack.push(keyindexvar);
-
-                    let mut s = unsafe { sets0.get_unchecked_mut(h0 as usize) };
-                    s.xor_mask ^= hash;
-                    s.count -= 1;
-                    if s.count == 1 {
-                        q0.push(KeyIndex {
-                            index: h0,
-                            hash: s.xor_mask,
-                        })
-                    }
-
-                    let mut s = unsafe { sets2.get_unchecked_mut(h2 as usize) };
-                    s.xor_mask ^= hash;
-                    s.count -= 1;
-                    if s.count == 1 {
-                        q2.push(KeyIndex {
-                            index: h2,
-                            hash: s.xor_mask,
-                        })
-                    }
-                }
-                while let Some(mut keyindexvar) = q2.pop() {
-                    if sets2[keyindexvar.index as usize].count == 0 {
-                        continue;
-                    }
-                    let hash = keyindexvar.hash;
-                    let h0 = self.geth0(hash);
-                    let h1 = self.geth1(hash);
-                    keyindexvar.index += 2 * self.block_length;
-                    stack.push(keyindexvar);
-
-                    let mut s = unsafe { sets0.get_unchecked_mut(h0 as usize) };
-                    s.xor_mask ^= hash;
-                    s.count -= 1;
-                    if s.count == 1 {
-                        q0.push(KeyIndex {
-                            index: h0,
-                            hash: s.xor_mask,
-                        })
-                    }
-                    let mut s = unsafe { sets1.get_unchecked_mut(h1 as usize) };
-                    s.xor_mask ^= hash;
-                    s.count -= 1;
-                    if s.count == 1 {
-                        q1.push(KeyIndex {
-                            index: h1,
-                            hash: s.xor_mask,
-                        })
-                    }
-                }
-            }
-
-            if stack.len() == size {
-                break;
-            }
-
-            for item in sets0.iter_mut() {
-                *item = Default::default();
-            }
-            for item in sets1.iter_mut() {
-                *item = Default::default();
-            }
-            for item in sets2.iter_mut() {
-                *item = Default::default();
-            }
-            self.seed = splitmix64(&mut rngcounter)
-        }
-
-        while let Some(ki) = stack.pop() {
-            let mut val = fingerprint(ki.hash) as u8;
-            if ki.index < self.block_length {
-                let h1 = (self.geth1(ki.hash) + self.block_length) as usize;
-                let h2 = (self.geth2(ki.hash) + 2 * self.block_length) as usize;
-                val ^= self.finger_prints[h1] ^ self.finger_prints[h2];
-            } else if ki.index < 2 * self.block_length {
-                let h0 = self.geth0(ki.hash) as usize;
-                let h2 = (self.geth2(ki.hash) + 2 * self.block_length) as usize;
-                val ^= self.finger_prints[h0] ^ self.finger_prints[h2];
-            } else {
-                let h0 = self.geth0(ki.hash) as usize;
-                let h1 = (self.geth1(ki.hash) + self.block_length) as usize;
-                val ^= self.finger_prints[h0] ^ self.finger_prints[h1]
-            }
-            self.finger_prints[ki.index as usize] = val;
-        }
-    }
-
-    /// Contains tell you whether the key is likely part of the set.
-    pub fn contains<T: ?Sized + Hash>(&self, key: &T) -> bool {
-        let hashed_key = {
-            let mut hasher = self.hash_builder.build_hasher();
-            key.hash(&mut hasher);
-            hasher.finish()
-        };
-        self.contains_key(hashed_key)
-    }
-
-    pub fn contains_key(&self, key: u64) -> bool {
-        let hash = mixsplit(key, self.seed);
-        let f = fingerprint(hash) as u8;
-        let r0 = hash as u32;
-        let r1 = hash.rotate_left(21) as u32;
-        let r2 = hash.rotate_left(42) as u32;
-        let h0 = reduce(r0, self.block_length) as usize;
-        let h1 = (reduce(r1, self.block_length) + self.block_length) as usize;
-        let h2 = (reduce(r2, self.block_length) + 2 * self.block_length) as usize;
-        f == (self.finger_prints[h0] ^ self.finger_prints[h1] ^ self.finger_prints[h2])
-    }
-
-    fn geth0h1h2(&self, k: u64) -> Hashes {
-        let h = mixsplit(k, self.seed);
-        Hashes {
-            h,
-            h0: reduce(h as u32, self.block_length),
-            h1: reduce(h.rotate_left(21) as u32, self.block_length),
-            h2: reduce(h.rotate_left(42) as u32, self.block_length),
-        }
-    }
-
-    fn geth0(&self, hash: u64) -> u32 {
-        let r0 = hash as u32;
-        reduce(r0, self.block_length)
-    }
-
-    fn geth1(&self, hash: u64) -> u32 {
-        let r1 = hash.rotate_left(21) as u32;
-        reduce(r1, self.block_length)
-    }
-
-    fn geth2(&self, hash: u64) -> u32 {
-        let r2 = hash.rotate_left(42) as u32;
-        reduce(r2, self.block_length)
-    }
+    /// Decode fingerprint at `index` from big-endian encoded `buf`.
+    fn decode_at(buf: &[u8], index: usize) -> Self;
 }
 
-impl<H> Xor8<H>
-where
-    H: BuildHasher,
-{
-    /// File signature write on first 4 bytes of file.
-    /// ^ stands for xor
-    /// TL stands for filter
-    /// 1 stands for version 1
-    const SIGNATURE_V1: [u8; 4] = [b'^', b'T', b'L', 1];
-
-    /// METADATA_LENGTH is size that required to write size of all the
-    /// metadata of the serialized filter.
-    // signature length + seed length + block-length +
-    //      fingerprint length + fingerprint size
-    const METADATA_LENGTH: usize = 4 + 8 + 4 + 4;
-
-    /// Write to file in binary format
-    /// TODO Add chechsum of finger_prints into file headers
-    pub fn write_file(&self, path: &ffi::OsStr) -> io::Result<usize> {
-        let mut f = fs::File::create(path)?;
-        let buf = self.to_bytes();
-        f.write_all(&buf)?;
-        Ok(buf.len())
+impl Fingerprint for u8 {
+    const SIZE: usize = 1;
+
+    fn from_hash(hash: u64) -> u8 {
+        fingerprint(hash) as u8
     }
 
-    /// Read from file in binary format
-    pub fn read_file(path: &ffi::OsStr) -> io::Result<Self>
-    where
-        H: Default,
-    {
-        let mut f = fs::File::open(path)?;
-        let mut data = Vec::new();
-        f.read_to_end(&mut data)?;
-        Self::from_bytes(data)
+    fn encode(fps: &[u8], buf: &mut Vec<u8>) {
+        buf.extend_from_slice(fps)
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let capacity = Self::METADATA_LENGTH + self.finger_prints.len();
-        let mut buf: Vec<u8> = Vec::with_capacity(capacity);
-        buf.extend_from_slice(&Xor8::<H>::SIGNATURE_V1);
-        buf.extend_from_slice(&self.seed.to_be_bytes());
-        buf.extend_from_slice(&self.block_length.to_be_bytes());
-        buf.extend_from_slice(&(self.finger_prints.len() as u32).to_be_bytes());
-        buf.extend_from_slice(&self.finger_prints);
-        buf
+    fn decode(buf: &[u8]) -> Vec<u8> {
+        buf.to_vec()
     }
 
-    pub fn from_bytes(buf: Vec<u8>) -> io::Result<Self>
-    where
-        H: Default,
-    {
-        // validate the buf first.
-        if Self::METADATA_LENGTH > buf.len() {
-            return Err(Error::new(ErrorKind::InvalidData, "invalid byte slice"));
-        }
-        if buf[..4] != Xor8::<H>::SIGNATURE_V1 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "File signature incorrect",
-            ));
-        }
-        let fp_len = u32::from_be_bytes(buf[16..20].try_into().unwrap()) as usize;
-        if buf[20..].len() < fp_len {
-            return Err(Error::new(ErrorKind::InvalidData, "invalid byte slice"));
-        }
-        Ok(Xor8 {
-            keys: Default::default(),
-            hash_builder: H::default(),
-            seed: u64::from_be_bytes(buf[4..12].try_into().unwrap()),
-            block_length: u32::from_be_bytes(buf[12..16].try_into().unwrap()),
-            finger_prints: buf[20..].to_vec(),
-        })
+    #[inline]
+    fn decode_at(buf: &[u8], index: usize) -> u8 {
+        buf[index]
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rand::{prelude::random, rngs::SmallRng, Rng, SeedableRng};
-
-    #[test]
-    fn test_basic1() {
-        let seed: u128 = random();
-        println!("test_basic1 seed {}", seed);
-        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
-
-        let testsize = 1_000_000;
-        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
-        keys.resize(testsize, Default::default());
-        for key in keys.iter_mut() {
-            *key = rng.gen();
-        }
-
-        let filter = {
-            let mut filter = Xor8::<RandomState>::new();
-            filter.populate(&keys);
-            filter.build();
-            filter
-        };
-
-        for key in keys.iter() {
-            assert!(filter.contains(key), "key {} not present", key);
-        }
+macro_rules! impl_fingerprint {
+    ($($type:ty),*) => {$(
+        impl Fingerprint for $type {
+            const SIZE: usize = std::mem::size_of::<$type>();
 
-        let (falsesize, mut matches) = (10_000_000, 0_f64);
-        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
-        println!("test_basic1 bits per entry {} bits", bpv);
-        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
-
-        for _ in 0..falsesize {
-            if filter.contains(&rng.gen::<u64>()) {
-                matches += 1_f64;
+            fn from_hash(hash: u64) -> $type {
+                fingerprint(hash) as $type
             }
-        }
-        let fpp = matches * 100.0 / (falsesize as f64);
-        println!("test_basic1 false positive rate {}%", fpp);
-        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
-    }
-
-    #[test]
-    fn test_basic2() {
-        let seed: u128 = random();
-        println!("test_basic2 seed {}", seed);
-        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
-
-        let testsize = 1_000_000;
-        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
-        keys.resize(testsize, Default::default());
-        for key in keys.iter_mut() {
-            *key = rng.gen();
-        }
-
-        let filter = {
-            let mut filter = Xor8::<RandomState>::new();
-            filter.populate_keys(&keys);
-            filter.build();
-            filter
-        };
 
-        for key in keys.into_iter() {
-            assert!(filter.contains_key(key), "key {} not present", key);
-        }
-
-        let (falsesize, mut matches) = (10_000_000, 0_f64);
-        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
-        println!("test_basic2 bits per entry {} bits", bpv);
-        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
-
-        for _ in 0..falsesize {
-            if filter.contains(&rng.gen::<u64>()) {
-                matches += 1_f64;
+            fn encode(fps: &[$type], buf: &mut Vec<u8>) {
+                fps.iter().for_each(|fp| buf.extend_from_slice(&fp.to_be_bytes()))
             }
-        }
-        let fpp = matches * 100.0 / (falsesize as f64);
-        println!("test_basic2 false positive rate {}%", fpp);
-        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
-    }
 
-    #[test]
-    fn test_basic3() {
-        let mut seed: u64 = random();
-        println!("test_basic3 seed {}", seed);
-
-        let testsize = 100_000;
-        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
-        keys.resize(testsize, Default::default());
-        for key in keys.iter_mut() {
-            *key = splitmix64(&mut seed);
-        }
-
-        let filter = {
-            let mut filter = Xor8::<RandomState>::new();
-            keys.iter().for_each(|key| filter.insert(key));
-            filter.build();
-            filter
-        };
-
-        for key in keys.iter() {
-            assert!(filter.contains(key), "key {} not present", key);
-        }
-
-        let (falsesize, mut matches) = (10_000_000, 0_f64);
-        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
-        println!("test_basic3 bits per entry {} bits", bpv);
-        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
-
-        for _ in 0..falsesize {
-            let v = splitmix64(&mut seed);
-            if filter.contains(&v) {
-                matches += 1_f64;
+            fn decode(buf: &[u8]) -> Vec<$type> {
+                buf.chunks_exact(Self::SIZE)
+                    .map(|fp| <$type>::from_be_bytes(fp.try_into().unwrap()))
+                    .collect()
             }
-        }
-        let fpp = matches * 100.0 / (falsesize as f64);
-        println!("test_basic3 false positive rate {}%", fpp);
-        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
-    }
-
-    #[test]
-    fn test_basic4() {
-        let seed: u128 = random();
-        println!("test_basic4 seed {}", seed);
-        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
-
-        let testsize = 1_000_000;
-        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
-        keys.resize(testsize, Default::default());
-        for key in keys.iter_mut() {
-            *key = rng.gen();
-        }
-
-        let filter = {
-            let mut filter = Xor8::<BuildHasherDefault>::new();
-            filter.populate(&keys);
-            filter.build();
-            filter
-        };
-
-        for key in keys.iter() {
-            assert!(filter.contains(key), "key {} not present", key);
-        }
-
-        let (falsesize, mut matches) = (10_000_000, 0_f64);
-        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
-        println!("test_basic4 bits per entry {} bits", bpv);
-        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
 
-        for _ in 0..falsesize {
-            if filter.contains(&rng.gen::<u64>()) {
-                matches += 1_f64;
+            #[inline]
+            fn decode_at(buf: &[u8], index: usize) -> $type {
+                let off = index * Self::SIZE;
+                <$type>::from_be_bytes(buf[off..(off + Self::SIZE)].try_into().unwrap())
             }
         }
-        let fpp = matches * 100.0 / (falsesize as f64);
-        println!("test_basic4 false positive rate {}%", fpp);
-        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
-    }
-
-    #[test]
-    fn test_basic5() {
-        let seed: u128 = random();
-        println!("test_basic5 seed {}", seed);
-        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
-
-        let testsize = 1_000_000;
-        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
-        keys.resize(testsize, Default::default());
-        for key in keys.iter_mut() {
-            *key = rng.gen();
-        }
-
-        let filter = {
-            let mut filter = Xor8::<BuildHasherDefault>::new();
-            filter.populate_keys(&keys);
-            filter.build();
-            filter
-        };
+    )*};
+}
 
-        for key in keys.into_iter() {
-            assert!(filter.contains_key(key), "key {} not present", key);
-        }
+impl_fingerprint!(u16, u32);
 
-        let (falsesize, mut matches) = (10_000_000, 0_f64);
-        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
-        println!("test_basic5 bits per entry {} bits", bpv);
-        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
+mod sealed {
+    pub trait Sealed {}
 
-        for _ in 0..falsesize {
-            if filter.contains(&rng.gen::<u64>()) {
-                matches += 1_f64;
-            }
-        }
-        let fpp = matches * 100.0 / (falsesize as f64);
-        println!("test_basic5 false positive rate {}%", fpp);
-        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
-    }
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
 
-    #[test]
-    fn test_basic6() {
-        let mut seed: u64 = random();
-        println!("test_basic6 seed {}", seed);
+#[derive(Clone, Default)]
+struct XorSet {
+    xor_mask: u64,
+    count: u32,
+}
 
-        let testsize = 100_000;
-        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
-        keys.resize(testsize, Default::default());
-        for key in keys.iter_mut() {
-            *key = splitmix64(&mut seed);
-        }
+#[derive(Default)]
+struct Hashes {
+    h: u64,
+    h0: u32,
+    h1: u32,
+    h2: u32,
+}
 
-        let filter = {
-            let mut filter = Xor8::<BuildHasherDefault>::new();
-            keys.iter().for_each(|key| filter.insert(key));
-            filter.build();
-            filter
-        };
+#[derive(Clone, Copy, Default)]
+struct KeyIndex {
+    hash: u64,
+    index: u32,
+}
 
-        for key in keys.iter() {
-            assert!(filter.contains(key), "key {} not present", key);
-        }
+/// Default hash builder for filters, builds [SipHasher13] with zero keys.
+///
+/// Unlike [std::collections::hash_map::DefaultHasher], its output does
+/// not change across Rust releases and platforms, so filters persisted with
+/// it can be read back and queried by later builds.
+#[derive(Clone, Default)]
+pub struct BuildHasherDefault {
+    hasher: hash::BuildHasherDefault<SipHasher13>,
+}
 
-        let (falsesize, mut matches) = (10_000_000, 0_f64);
-        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
-        println!("test_basic6 bits per entry {} bits", bpv);
-        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
+impl BuildHasher for BuildHasherDefault {
+    type Hasher = SipHasher13;
 
-        for _ in 0..falsesize {
-            let v = splitmix64(&mut seed);
-            if filter.contains(&v) {
-                matches += 1_f64;
-            }
-        }
-        let fpp = matches * 100.0 / (falsesize as f64);
-        println!("test_basic6 false positive rate {}%", fpp);
-        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
+    fn build_hasher(&self) -> Self::Hasher {
+        self.hasher.build_hasher()
     }
 }
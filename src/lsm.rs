@@ -0,0 +1,386 @@
+use std::{
+    hash::{BuildHasher, Hash},
+    iter, mem, thread,
+};
+
+use crate::{BuildHasherDefault, Fingerprint, XorFilter, XorFilterBuilder};
+
+// Default number of digests buffered in the delta, before building them
+// into a level.
+const DEFAULT_DELTA_LIMIT: usize = 4096;
+
+// Newest levels are merged with an older level, while the older level is
+// less than FANOUT times their size.
+const FANOUT: usize = 4;
+
+/// Type XorLsm is an updatable set of xor filters, that accepts new keys
+/// after it is built.
+///
+/// [XorFilter] is immutable once built. XorLsm buffers new digests in a
+/// small, sorted, delta. When the delta is full, its digests are built into
+/// a new [XorFilter] level. Levels are merged log-structured style, newest
+/// levels are merged together, along with older levels that are less than
+/// 4 times their size, keeping O(log n) levels. Merging rebuilds the filter,
+/// so every level retains the digests of its keys, costing 8 bytes per key
+/// on top of its fingerprints.
+///
+/// By default merges run in a background thread, while the levels being
+/// merged remain queryable, refer to [XorLsm::set_background]. Lookups
+/// check the delta and every level, hence the false positive rate is
+/// roughly the number of levels times that of a single [XorFilter].
+pub struct XorLsm<F, H = BuildHasherDefault>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    delta: Vec<u64>,
+    delta_limit: usize,
+    background: bool,
+    levels: Vec<Level<F>>,
+    merging: Option<Merge<F>>,
+    hash_builder: H,
+}
+
+/// XorLsm with 8-bit fingerprints.
+pub type Xor8Lsm<H = BuildHasherDefault> = XorLsm<u8, H>;
+
+/// XorLsm with 16-bit fingerprints.
+pub type Xor16Lsm<H = BuildHasherDefault> = XorLsm<u16, H>;
+
+/// XorLsm with 32-bit fingerprints.
+pub type Xor32Lsm<H = BuildHasherDefault> = XorLsm<u32, H>;
+
+// Filter built from sorted and de-duplicated digests, `keys` is empty
+// while the level is being merged in background.
+struct Level<F>
+where
+    F: Fingerprint,
+{
+    keys: Vec<u64>,
+    filter: XorFilter<F>,
+}
+
+// Merge of `count` levels from levels[start], holding `len` digests.
+struct Merge<F>
+where
+    F: Fingerprint,
+{
+    start: usize,
+    count: usize,
+    len: usize,
+    handle: thread::JoinHandle<Level<F>>,
+}
+
+impl<F> Level<F>
+where
+    F: Fingerprint,
+{
+    fn new(keys: Vec<u64>) -> Level<F> {
+        let filter = XorFilterBuilder::new().build_keys(&keys);
+        Level { keys, filter }
+    }
+
+    fn merge(runs: Vec<Vec<u64>>) -> Level<F> {
+        let mut keys = runs.concat();
+        keys.sort_unstable();
+        keys.dedup();
+        Level::new(keys)
+    }
+}
+
+impl<F, H> Default for XorLsm<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher + Default,
+{
+    fn default() -> Self {
+        XorLsm::with_hasher(H::default())
+    }
+}
+
+impl<F, H> XorLsm<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher + Default,
+{
+    /// New XorLsm instance initialized with `DefaultHasher`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<F, H> XorLsm<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// New XorLsm instance initialized with supplied `hasher`.
+    pub fn with_hasher(hash_builder: H) -> Self {
+        XorLsm {
+            delta: Vec::default(),
+            delta_limit: DEFAULT_DELTA_LIMIT,
+            background: true,
+            levels: Vec::default(),
+            merging: None,
+            hash_builder,
+        }
+    }
+
+    /// Set the number of digests buffered before they are built into a
+    /// new level, defaults to 4096.
+    pub fn set_delta_limit(&mut self, delta_limit: usize) -> &mut Self {
+        self.delta_limit = delta_limit.max(1);
+        self
+    }
+
+    /// Merge levels in a background thread, when `background` is true,
+    /// which is the default. Otherwise levels are merged by the call to
+    /// insert that fills the delta.
+    pub fn set_background(&mut self, background: bool) -> &mut Self {
+        self.background = background;
+        self
+    }
+
+    /// Number of digests in the delta and levels. Digests added to more
+    /// than one level are counted once per level, until they are merged.
+    pub fn len(&self) -> usize {
+        let n: usize = self.levels.iter().map(|l| l.keys.len()).sum();
+        let m = self.merging.as_ref().map(|m| m.len).unwrap_or(0);
+        self.delta.len() + n + m
+    }
+
+    /// Return true if no keys were added.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of filter levels, excluding the delta.
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Hash builder used to compute digests of keys.
+    pub fn hash_builder(&self) -> &H {
+        &self.hash_builder
+    }
+
+    /// Check whether `key` is present in the set.
+    pub fn contains<T: ?Sized + Hash>(&self, key: &T) -> bool {
+        let hashed_key = self.hash_builder.hash_one(key);
+        self.contains_key(hashed_key)
+    }
+
+    /// Check whether pre-computed 64-bit digest is present in the set.
+    pub fn contains_key(&self, digest: u64) -> bool {
+        self.delta.binary_search(&digest).is_ok()
+            || self.levels.iter().any(|l| l.filter.contains_key(digest))
+    }
+
+    /// Build digests buffered in the delta into a new level, and wait for
+    /// all merges to complete.
+    pub fn flush(&mut self) {
+        if !self.delta.is_empty() {
+            let keys = mem::take(&mut self.delta);
+            self.levels.push(Level::new(keys));
+        }
+        if let Some(merge) = self.merging.take() {
+            self.finish(merge);
+        }
+        while let Some(start) = self.pick_merge() {
+            self.merge_now(start);
+        }
+    }
+
+    /// Flush the delta and merge all levels into a single level, removing
+    /// digests added more than once.
+    pub fn compact(&mut self) {
+        self.flush();
+        if self.levels.len() > 1 {
+            self.merge_now(0);
+        }
+    }
+}
+
+impl<F, H> XorLsm<F, H>
+where
+    F: Fingerprint + Send + 'static,
+    H: BuildHasher,
+{
+    /// Insert 64-bit digest of a single key. Digest for the key shall
+    /// be generated using the default-hasher or via hasher supplied via
+    /// [XorLsm::with_hasher] method.
+    pub fn insert<T: ?Sized + Hash>(&mut self, key: &T) {
+        let hashed_key = self.hash_builder.hash_one(key);
+        self.insert_key(hashed_key)
+    }
+
+    /// Populate 64-bit digests for collection of keys.
+    pub fn populate<T: Hash>(&mut self, keys: &[T]) {
+        keys.iter().for_each(|key| self.insert(key))
+    }
+
+    /// Insert pre-computed 64-bit digest of a key.
+    pub fn insert_key(&mut self, digest: u64) {
+        if let Err(off) = self.delta.binary_search(&digest) {
+            self.delta.insert(off, digest);
+        }
+        if self.delta.len() < self.delta_limit {
+            return;
+        }
+
+        let keys = mem::take(&mut self.delta);
+        self.levels.push(Level::new(keys));
+        // wait for the merge, if levels piled up behind it.
+        match self.merging.take() {
+            Some(merge) if merge.handle.is_finished() => self.finish(merge),
+            Some(merge) if self.levels.len() >= merge.start + merge.count + FANOUT => {
+                self.finish(merge)
+            }
+            Some(merge) => self.merging = Some(merge),
+            None => (),
+        }
+        while let Some(start) = self.pick_merge() {
+            if self.background {
+                self.merge_background(start);
+                break;
+            }
+            self.merge_now(start);
+        }
+    }
+
+    /// Populate pre-computed 64-bit digests for keys.
+    pub fn populate_keys(&mut self, digests: &[u64]) {
+        digests.iter().for_each(|digest| self.insert_key(*digest))
+    }
+}
+
+impl<F, H> XorLsm<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    // Index of the oldest level to merge, along with all newer levels.
+    // Only one merge at a time.
+    fn pick_merge(&self) -> Option<usize> {
+        if self.merging.is_some() || self.levels.len() < 2 {
+            return None;
+        }
+        let mut start = self.levels.len() - 1;
+        let mut len = self.levels[start].keys.len();
+        while start > 0 && self.levels[start - 1].keys.len() < FANOUT * len {
+            start -= 1;
+            len += self.levels[start].keys.len();
+        }
+        if start < self.levels.len() - 1 {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    fn merge_now(&mut self, start: usize) {
+        let runs = self.levels.drain(start..).map(|l| l.keys).collect();
+        self.levels.push(Level::merge(runs));
+    }
+
+    // Merge levels from `start` in a background thread, the filters of
+    // those levels remain queryable until the merge is finished.
+    fn merge_background(&mut self, start: usize)
+    where
+        F: Send + 'static,
+    {
+        let runs: Vec<Vec<u64>> = self.levels[start..]
+            .iter_mut()
+            .map(|l| mem::take(&mut l.keys))
+            .collect();
+        let (count, len) = (runs.len(), runs.iter().map(|r| r.len()).sum());
+        let handle = thread::spawn(move || Level::merge(runs));
+        self.merging = Some(Merge {
+            start,
+            count,
+            len,
+            handle,
+        });
+    }
+
+    // Wait for `merge` and replace the merged levels with its result.
+    // Levels added meanwhile are after the merged levels.
+    fn finish(&mut self, merge: Merge<F>) {
+        let level = merge.handle.join().expect("merge thread panicked");
+        let end = merge.start + merge.count;
+        self.levels.splice(merge.start..end, iter::once(level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splitmix64;
+    use rand::prelude::random;
+
+    #[test]
+    fn test_lsm_insert() {
+        let mut seed: u64 = random();
+        println!("test_lsm_insert seed {}", seed);
+
+        let keys: Vec<u64> = (0..100_000).map(|_| splitmix64(&mut seed)).collect();
+
+        for background in [false, true] {
+            let mut filter = Xor16Lsm::<BuildHasherDefault>::new();
+            filter.set_delta_limit(1000).set_background(background);
+            assert!(filter.is_empty());
+
+            for (i, key) in keys.iter().enumerate() {
+                filter.insert_key(*key);
+                if i % 997 == 0 {
+                    // keys are visible as soon as they are inserted.
+                    for key in keys[..=i].iter().step_by(101) {
+                        assert!(filter.contains_key(*key), "key {} not present", key);
+                    }
+                }
+            }
+            // levels are merged log-structured, not one per delta.
+            assert!(filter.levels() < 20, "{} levels", filter.levels());
+            for key in keys.iter() {
+                assert!(filter.contains_key(*key), "key {} not present", key);
+            }
+
+            filter.flush();
+            assert!(filter.delta.is_empty() && filter.merging.is_none());
+
+            // duplicates are removed by merges.
+            filter.populate_keys(&keys[..5000]);
+            filter.compact();
+            assert_eq!(filter.levels(), 1);
+            assert_eq!(filter.len(), keys.len());
+            for key in keys.iter() {
+                assert!(filter.contains_key(*key), "key {} not present", key);
+            }
+
+            let mut fps = 0;
+            for _ in 0..100_000 {
+                if filter.contains_key(splitmix64(&mut seed)) {
+                    fps += 1;
+                }
+            }
+            println!("test_lsm_insert false positives {} in 100000", fps);
+            assert!(fps < 100, "{} false positives", fps);
+        }
+    }
+
+    #[test]
+    fn test_lsm_populate() {
+        let mut filter = Xor8Lsm::<BuildHasherDefault>::new();
+        let keys: Vec<String> = (0..10_000).map(|i| format!("key-{}", i)).collect();
+        filter.populate(&keys[..5000]);
+        filter.flush();
+        assert_eq!(filter.len(), 5000);
+        keys[5000..].iter().for_each(|key| filter.insert(key));
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+        filter.compact();
+        assert_eq!(filter.levels(), 1);
+        assert_eq!(filter.len(), keys.len());
+    }
+}
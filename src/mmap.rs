@@ -0,0 +1,158 @@
+use memmap2::Mmap;
+
+use std::{
+    ffi, fs,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+};
+
+use crate::xor::positions;
+use crate::{mixsplit, BuildHasherDefault, Error, Fingerprint, SerializableHasher};
+use crate::{XorFilter, XorFilterRef};
+
+/// Type XorFilterMmap is a read-only [XorFilter], written to file by
+/// [XorFilter::write_file], that is queried straight from the
+/// memory-mapped file.
+///
+/// Pages are loaded lazily by the operating system, and shared between
+/// processes mapping the same file. Refer to [XorFilter::open_mmap].
+pub struct XorFilterMmap<F, H = BuildHasherDefault>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    hash_builder: H,
+    seed: u64,
+    block_length: u32,
+    offset: usize,
+    mmap: Mmap,
+    _fp: PhantomData<F>,
+}
+
+/// Memory-mapped [Xor8](crate::Xor8).
+pub type Xor8Mmap<H = BuildHasherDefault> = XorFilterMmap<u8, H>;
+
+/// Memory-mapped [Xor16](crate::Xor16).
+pub type Xor16Mmap<H = BuildHasherDefault> = XorFilterMmap<u16, H>;
+
+/// Memory-mapped [Xor32](crate::Xor32).
+pub type Xor32Mmap<H = BuildHasherDefault> = XorFilterMmap<u32, H>;
+
+impl<F, H> XorFilter<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// Memory-map file, written by [XorFilter::write_file], and validate
+    /// its header. Fingerprints are not read into memory, lookups are
+    /// served from the mapping. Checksum, if any, is not verified, refer
+    /// to [XorFilterMmap::verify].
+    ///
+    /// The file must not be modified while it is mapped, doing so leads to
+    /// undefined behavior.
+    pub fn open_mmap(path: &ffi::OsStr) -> Result<XorFilterMmap<F, H>, Error>
+    where
+        H: SerializableHasher,
+    {
+        let file = fs::File::open(path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let (hash_builder, seed, block_length) = {
+            let fref = XorFilterRef::<F, H>::parse(&mmap, false)?;
+            (fref.hash_builder, fref.seed, fref.block_length)
+        };
+        let offset = XorFilter::<F, H>::read_header(&mmap)?.offset;
+        Ok(XorFilterMmap {
+            hash_builder,
+            seed,
+            block_length,
+            offset,
+            mmap,
+            _fp: PhantomData,
+        })
+    }
+}
+
+impl<F, H> XorFilterMmap<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// Hash builder used to compute digests of keys.
+    pub fn hash_builder(&self) -> &H {
+        &self.hash_builder
+    }
+
+    /// Seed mixed with digests of keys, to locate their fingerprints.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of fingerprints in each of the three blocks.
+    pub fn block_length(&self) -> u32 {
+        self.block_length
+    }
+
+    /// Contains tell you whether the key is likely part of the set.
+    pub fn contains<T: ?Sized + Hash>(&self, key: &T) -> bool {
+        let hashed_key = self.hash_builder.hash_one(key);
+        self.contains_key(hashed_key)
+    }
+
+    pub fn contains_key(&self, key: u64) -> bool {
+        let hash = mixsplit(key, self.seed);
+        let f = F::from_hash(hash);
+        let [h0, h1, h2] = positions(hash, self.block_length);
+        let fps = &self.mmap[self.offset..];
+        f == (F::decode_at(fps, h0) ^ F::decode_at(fps, h1) ^ F::decode_at(fps, h2))
+    }
+
+    /// Verify checksum of the mapped file, touches every page of the
+    /// mapping. Files in version 1 format carry no checksum and always
+    /// pass.
+    pub fn verify(&self) -> Result<(), Error> {
+        let hdr = XorFilter::<F, H>::read_header(&self.mmap)?;
+        hdr.verify(&self.mmap, (self.block_length as usize) * 3 * F::SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{splitmix64, Xor16, Xor16Builder, Xor8};
+    use rand::prelude::random;
+
+    #[test]
+    fn test_open_mmap() {
+        let mut seed: u64 = random();
+        println!("test_open_mmap seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let file_path = {
+            let mut fpath = std::env::temp_dir();
+            fpath.push("xorfilter-test-open-mmap");
+            fpath.into_os_string()
+        };
+
+        let mut builder = Xor16Builder::<BuildHasherDefault>::new();
+        builder.populate(&keys);
+        let filter = builder.build();
+        filter.write_file(&file_path).unwrap();
+
+        let mfilter = Xor16::<BuildHasherDefault>::open_mmap(&file_path).unwrap();
+        assert_eq!(mfilter.seed, filter.seed);
+        assert_eq!(mfilter.block_length, filter.block_length);
+        for key in keys.iter() {
+            assert!(mfilter.contains(key), "key {} not present", key);
+        }
+        for _ in 0..100_000 {
+            let key = splitmix64(&mut seed);
+            assert_eq!(mfilter.contains_key(key), filter.contains_key(key));
+        }
+
+        mfilter.verify().unwrap();
+
+        assert!(Xor8::<BuildHasherDefault>::open_mmap(&file_path).is_err());
+    }
+}
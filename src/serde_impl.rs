@@ -0,0 +1,157 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use std::convert::TryInto;
+
+use crate::hasher::make_hasher;
+use crate::{Error, Fingerprint, SerializableHasher, XorFilter};
+
+// Serialized form of XorFilter. Fingerprints are encoded in big-endian
+// as a single byte-string, same as in XorFilter::to_bytes.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "XorFilter")]
+pub(crate) struct Repr {
+    seed: u64,
+    block_length: u32,
+    #[serde(with = "serde_bytes")]
+    hasher: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    hasher_params: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    finger_prints: Vec<u8>,
+}
+
+impl Repr {
+    pub(crate) fn from_filter<F, H>(filter: &XorFilter<F, H>) -> Repr
+    where
+        F: Fingerprint,
+        H: SerializableHasher,
+    {
+        let mut finger_prints = Vec::with_capacity(filter.finger_prints.len() * F::SIZE);
+        F::encode(&filter.finger_prints, &mut finger_prints);
+        Repr {
+            seed: filter.seed,
+            block_length: filter.block_length,
+            hasher: H::ID.to_vec(),
+            hasher_params: filter.hash_builder.to_params(),
+            finger_prints,
+        }
+    }
+}
+
+/// Serialize filter as a struct of `seed`, `block_length`, hash builder's
+/// identity and parameters, and `finger_prints` encoded as big-endian
+/// bytes.
+impl<F, H> Serialize for XorFilter<F, H>
+where
+    F: Fingerprint,
+    H: SerializableHasher,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Repr::from_filter(self).serialize(serializer)
+    }
+}
+
+/// Deserialize filter serialized by its [Serialize] implementation,
+/// validating it like [XorFilter::from_bytes].
+impl<'de, F, H> Deserialize<'de> for XorFilter<F, H>
+where
+    F: Fingerprint,
+    H: SerializableHasher,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = Repr::deserialize(deserializer)?;
+        XorFilter::from_repr(repr).map_err(de::Error::custom)
+    }
+}
+
+impl<F, H> XorFilter<F, H>
+where
+    F: Fingerprint,
+    H: SerializableHasher,
+{
+    pub(crate) fn from_repr(repr: Repr) -> Result<Self, Error> {
+        let block_length = repr.block_length;
+        let fp_len = repr.finger_prints.len() / F::SIZE;
+        if block_length == 0 {
+            return Err(Error::ZeroBlockLength);
+        }
+        let n = (block_length as usize)
+            .checked_mul(3 * F::SIZE)
+            .ok_or(Error::Overflow)?;
+        if repr.finger_prints.len() != n {
+            return Err(Error::BlockLengthMismatch {
+                block_length,
+                fp_len,
+            });
+        }
+        let id: [u8; 4] = match repr.hasher.as_slice().try_into() {
+            Ok(id) => id,
+            Err(_) => return Err(Error::InvalidHasherParams(H::ID)),
+        };
+        let hash_builder = make_hasher(id, &repr.hasher_params)?;
+
+        let finger_prints = F::decode(&repr.finger_prints);
+        Ok(XorFilter::from_parts(
+            hash_builder,
+            repr.seed,
+            block_length,
+            finger_prints,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        splitmix64, BuildHasherDefault, BuildSipHasher13, Xor16, Xor16Builder, Xor8,
+        Xor8Builder,
+    };
+    use rand::prelude::random;
+
+    #[test]
+    fn test_serde() {
+        let mut seed: u64 = random();
+        println!("test_serde seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = Xor8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+
+        let data = bincode::serialize(&filter).unwrap();
+        assert!(
+            data.len() < filter.finger_prints.len() + 64,
+            "{}",
+            data.len()
+        );
+        let filter_read: Xor8 = bincode::deserialize(&data).unwrap();
+        assert!(filter_read == filter, "Filter unequals after bincode");
+        assert!(bincode::deserialize::<Xor16>(&data).is_err());
+
+        let data = serde_json::to_string(&filter).unwrap();
+        let filter_read: Xor8 = serde_json::from_str(&data).unwrap();
+        assert!(filter_read == filter, "Filter unequals after json");
+        for key in keys.iter() {
+            assert!(filter_read.contains_key(*key), "key {} not present", key);
+        }
+
+        let hash_builder = BuildSipHasher13::new(seed, !seed);
+        let mut builder = Xor16Builder::with_hasher(hash_builder.clone());
+        builder.populate(&keys);
+        let filter = builder.build();
+
+        let data = bincode::serialize(&filter).unwrap();
+        assert!(bincode::deserialize::<Xor16>(&data).is_err());
+        let filter_read: Xor16<BuildSipHasher13> = bincode::deserialize(&data).unwrap();
+        assert!(filter_read == filter, "Filter unequals after bincode");
+        assert_eq!(filter_read.hash_builder, hash_builder);
+        for key in keys.iter() {
+            assert!(filter_read.contains(key), "key {} not present", key);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+// Vectorized hashing for bulk lookups.
+//
+// Digests are mixed with the filter's seed by `mixsplit`, and reduced to
+// fingerprint positions, four keys at a time with AVX2 and two keys at a
+// time with SSE4.1, when the CPU supports them. AVX2 and SSE4.1 lack a
+// 64-bit multiply, it is composed from 32-bit multiplies, so results are
+// exactly the same as the scalar `mixsplit` and `positions`.
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::{mixsplit, xor::positions};
+
+/// Hash of a key and the positions of its three fingerprints.
+pub(crate) type Probe = (u64, [usize; 3]);
+
+/// Instruction set used to compute [Probe]s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Isa {
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    #[cfg(target_arch = "x86_64")]
+    Sse41,
+    Scalar,
+}
+
+impl Isa {
+    /// Detect the widest instruction set supported by this CPU.
+    pub(crate) fn detect() -> Isa {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Isa::Avx2;
+            } else if is_x86_feature_detected!("sse4.1") {
+                return Isa::Sse41;
+            }
+        }
+        Isa::Scalar
+    }
+
+    /// Compute probes for `keys` into `out`, for a filter of `seed` and
+    /// `block_length`. Only `min(keys.len(), out.len())` keys are hashed.
+    #[inline]
+    pub(crate) fn probes(
+        self,
+        keys: &[u64],
+        seed: u64,
+        block_length: u32,
+        out: &mut [Probe],
+    ) {
+        match self {
+            // safe, feature was detected at runtime.
+            #[cfg(target_arch = "x86_64")]
+            Isa::Avx2 => unsafe { probes_avx2(keys, seed, block_length, out) },
+            #[cfg(target_arch = "x86_64")]
+            Isa::Sse41 => unsafe { probes_sse41(keys, seed, block_length, out) },
+            Isa::Scalar => probes_scalar(keys, seed, block_length, out),
+        }
+    }
+}
+
+fn probes_scalar(keys: &[u64], seed: u64, block_length: u32, out: &mut [Probe]) {
+    for (probe, key) in out.iter_mut().zip(keys.iter()) {
+        let hash = mixsplit(*key, seed);
+        *probe = (hash, positions(hash, block_length));
+    }
+}
+
+const M1: u64 = 0xff51_afd7_ed55_8ccd;
+const M2: u64 = 0xc4ce_b9fe_1a85_ec53;
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn probes_avx2(keys: &[u64], seed: u64, block_length: u32, out: &mut [Probe]) {
+    // lane-wise 64-bit wrapping multiply.
+    #[inline(always)]
+    unsafe fn mul64(a: __m256i, b: __m256i) -> __m256i {
+        let lo = _mm256_mul_epu32(a, b);
+        let hi = _mm256_add_epi64(
+            _mm256_mul_epu32(_mm256_srli_epi64(a, 32), b),
+            _mm256_mul_epu32(a, _mm256_srli_epi64(b, 32)),
+        );
+        _mm256_add_epi64(lo, _mm256_slli_epi64(hi, 32))
+    }
+
+    // lane-wise murmur64 finalizer.
+    #[inline(always)]
+    unsafe fn murmur64(mut h: __m256i, m1: __m256i, m2: __m256i) -> __m256i {
+        h = _mm256_xor_si256(h, _mm256_srli_epi64(h, 33));
+        h = mul64(h, m1);
+        h = _mm256_xor_si256(h, _mm256_srli_epi64(h, 33));
+        h = mul64(h, m2);
+        _mm256_xor_si256(h, _mm256_srli_epi64(h, 33))
+    }
+
+    // lane-wise reduce, of the lower 32 bits of `h`, and offset by `off`.
+    #[inline(always)]
+    unsafe fn reduce(h: __m256i, n: __m256i, off: __m256i) -> __m256i {
+        _mm256_add_epi64(_mm256_srli_epi64(_mm256_mul_epu32(h, n), 32), off)
+    }
+
+    let n = keys.len().min(out.len());
+    let (m1, m2) = (_mm256_set1_epi64x(M1 as i64), _mm256_set1_epi64x(M2 as i64));
+    let seed_v = _mm256_set1_epi64x(seed as i64);
+    let bl = _mm256_set1_epi64x(block_length as i64);
+    let (off0, off1, off2) = (_mm256_setzero_si256(), bl, _mm256_add_epi64(bl, bl));
+
+    let (mut hs, mut h0, mut h1, mut h2) =
+        ([0_u64; 4], [0_u64; 4], [0_u64; 4], [0_u64; 4]);
+    let mut i = 0;
+    while i + 4 <= n {
+        let k = _mm256_loadu_si256(keys.as_ptr().add(i) as *const __m256i);
+        let h = murmur64(_mm256_add_epi64(k, seed_v), m1, m2);
+        let r1 = _mm256_or_si256(_mm256_slli_epi64(h, 21), _mm256_srli_epi64(h, 43));
+        let r2 = _mm256_or_si256(_mm256_slli_epi64(h, 42), _mm256_srli_epi64(h, 22));
+        _mm256_storeu_si256(hs.as_mut_ptr() as *mut __m256i, h);
+        _mm256_storeu_si256(h0.as_mut_ptr() as *mut __m256i, reduce(h, bl, off0));
+        _mm256_storeu_si256(h1.as_mut_ptr() as *mut __m256i, reduce(r1, bl, off1));
+        _mm256_storeu_si256(h2.as_mut_ptr() as *mut __m256i, reduce(r2, bl, off2));
+        for j in 0..4 {
+            out[i + j] = (hs[j], [h0[j] as usize, h1[j] as usize, h2[j] as usize]);
+        }
+        i += 4;
+    }
+    probes_scalar(&keys[i..n], seed, block_length, &mut out[i..n]);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn probes_sse41(keys: &[u64], seed: u64, block_length: u32, out: &mut [Probe]) {
+    // lane-wise 64-bit wrapping multiply.
+    #[inline(always)]
+    unsafe fn mul64(a: __m128i, b: __m128i) -> __m128i {
+        let lo = _mm_mul_epu32(a, b);
+        let hi = _mm_add_epi64(
+            _mm_mul_epu32(_mm_srli_epi64(a, 32), b),
+            _mm_mul_epu32(a, _mm_srli_epi64(b, 32)),
+        );
+        _mm_add_epi64(lo, _mm_slli_epi64(hi, 32))
+    }
+
+    // lane-wise murmur64 finalizer.
+    #[inline(always)]
+    unsafe fn murmur64(mut h: __m128i, m1: __m128i, m2: __m128i) -> __m128i {
+        h = _mm_xor_si128(h, _mm_srli_epi64(h, 33));
+        h = mul64(h, m1);
+        h = _mm_xor_si128(h, _mm_srli_epi64(h, 33));
+        h = mul64(h, m2);
+        _mm_xor_si128(h, _mm_srli_epi64(h, 33))
+    }
+
+    // lane-wise reduce, of the lower 32 bits of `h`, and offset by `off`.
+    #[inline(always)]
+    unsafe fn reduce(h: __m128i, n: __m128i, off: __m128i) -> __m128i {
+        _mm_add_epi64(_mm_srli_epi64(_mm_mul_epu32(h, n), 32), off)
+    }
+
+    let n = keys.len().min(out.len());
+    let (m1, m2) = (_mm_set1_epi64x(M1 as i64), _mm_set1_epi64x(M2 as i64));
+    let seed_v = _mm_set1_epi64x(seed as i64);
+    let bl = _mm_set1_epi64x(block_length as i64);
+    let (off0, off1, off2) = (_mm_setzero_si128(), bl, _mm_add_epi64(bl, bl));
+
+    let mut i = 0;
+    while i + 2 <= n {
+        let k = _mm_loadu_si128(keys.as_ptr().add(i) as *const __m128i);
+        let h = murmur64(_mm_add_epi64(k, seed_v), m1, m2);
+        let r1 = _mm_or_si128(_mm_slli_epi64(h, 21), _mm_srli_epi64(h, 43));
+        let r2 = _mm_or_si128(_mm_slli_epi64(h, 42), _mm_srli_epi64(h, 22));
+        let (h0, h1, h2) = (
+            reduce(h, bl, off0),
+            reduce(r1, bl, off1),
+            reduce(r2, bl, off2),
+        );
+        out[i] = (
+            _mm_extract_epi64(h, 0) as u64,
+            [
+                _mm_extract_epi64(h0, 0) as usize,
+                _mm_extract_epi64(h1, 0) as usize,
+                _mm_extract_epi64(h2, 0) as usize,
+            ],
+        );
+        out[i + 1] = (
+            _mm_extract_epi64(h, 1) as u64,
+            [
+                _mm_extract_epi64(h0, 1) as usize,
+                _mm_extract_epi64(h1, 1) as usize,
+                _mm_extract_epi64(h2, 1) as usize,
+            ],
+        );
+        i += 2;
+    }
+    probes_scalar(&keys[i..n], seed, block_length, &mut out[i..n]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splitmix64;
+    use rand::prelude::random;
+
+    #[test]
+    fn test_probes() {
+        let mut seed: u64 = random();
+        println!("test_probes seed {}", seed);
+
+        let mut isas = vec![Isa::Scalar];
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                isas.push(Isa::Avx2);
+            }
+            if is_x86_feature_detected!("sse4.1") {
+                isas.push(Isa::Sse41);
+            }
+        }
+        assert!(isas.contains(&Isa::detect()));
+
+        let keys: Vec<u64> = (0..1001).map(|_| splitmix64(&mut seed)).collect();
+        let mut block_lengths = vec![1, 2, 3, 1230, u32::MAX / 3];
+        block_lengths.extend((0..10).map(|_| random::<u32>() / 3));
+        for block_length in block_lengths.into_iter() {
+            let fseed = splitmix64(&mut seed);
+            let mut refs = vec![(0, [0; 3]); keys.len()];
+            probes_scalar(&keys, fseed, block_length, &mut refs);
+            for (key, probe) in keys.iter().zip(refs.iter()) {
+                let hash = mixsplit(*key, fseed);
+                assert_eq!(*probe, (hash, positions(hash, block_length)));
+            }
+            for isa in isas.iter() {
+                // every length, to cover the scalar tail.
+                for n in 0..9 {
+                    let mut out = vec![(0, [0; 3]); n];
+                    isa.probes(&keys[..n], fseed, block_length, &mut out);
+                    assert_eq!(out, refs[..n], "{:?} {} {}", isa, block_length, n);
+                }
+                let mut out = vec![(0, [0; 3]); keys.len()];
+                isa.probes(&keys, fseed, block_length, &mut out);
+                assert_eq!(out, refs, "{:?} {}", isa, block_length);
+            }
+        }
+    }
+}
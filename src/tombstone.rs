@@ -0,0 +1,273 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    BuildHasherDefault, Fingerprint, XorFilter, XorFilterBuilder, DEFAULT_RNG_SEED,
+};
+
+/// Type XorTombstone pairs an [XorFilter] of keys, with a tombstone
+/// [XorFilter] of keys removed after the filter was built.
+///
+/// Digests of keys are retained, so that [XorTombstone::build] can rebuild
+/// the main filter without the removed keys, compacting the tombstones
+/// away. Removing a key rebuilds the tombstone filter, which is cheap as
+/// long as removals are few compared to keys.
+///
+/// With `ε` as the false positive rate of a single [XorFilter], about
+/// 0.3% for 8-bit fingerprints:
+///
+/// * Keys that were never added are reported present with a probability
+///   of about `ε`, same as [XorFilter].
+/// * Removed keys are never reported present.
+/// * Keys that are present and not removed are reported absent with a
+///   probability of about `ε`, while there are tombstones, when they
+///   collide with the tombstone filter. Such false negatives go away once
+///   the filter is rebuilt with [XorTombstone::build].
+pub struct XorTombstone<F, H = BuildHasherDefault>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    // sorted and de-duplicated digests in the main filter.
+    keys: Vec<u64>,
+    // digests inserted after the main filter was built.
+    pending: Vec<u64>,
+    // sorted digests removed after the main filter was built.
+    removed: Vec<u64>,
+    main: XorFilter<F, H>,
+    tombstones: Option<XorFilter<F>>,
+}
+
+/// XorTombstone with 8-bit fingerprints.
+pub type Xor8Tombstone<H = BuildHasherDefault> = XorTombstone<u8, H>;
+
+/// XorTombstone with 16-bit fingerprints.
+pub type Xor16Tombstone<H = BuildHasherDefault> = XorTombstone<u16, H>;
+
+/// XorTombstone with 32-bit fingerprints.
+pub type Xor32Tombstone<H = BuildHasherDefault> = XorTombstone<u32, H>;
+
+impl<F, H> Default for XorTombstone<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher + Default,
+{
+    fn default() -> Self {
+        XorTombstone::with_hasher(H::default())
+    }
+}
+
+impl<F, H> XorTombstone<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher + Default,
+{
+    /// New XorTombstone instance initialized with `DefaultHasher`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<F, H> XorTombstone<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// New XorTombstone instance initialized with supplied `hasher`.
+    pub fn with_hasher(hash_builder: H) -> Self {
+        XorTombstone {
+            keys: Vec::default(),
+            pending: Vec::default(),
+            removed: Vec::default(),
+            main: XorFilterBuilder::with_hasher(hash_builder).build_keys(&[]),
+            tombstones: None,
+        }
+    }
+
+    /// Insert 64-bit digest of a single key. Inserted keys are part of
+    /// the filter after the next call to [XorTombstone::build].
+    pub fn insert<T: ?Sized + Hash>(&mut self, key: &T) {
+        let hashed_key = self.main.hash_builder.hash_one(key);
+        self.pending.push(hashed_key);
+    }
+
+    /// Populate 64-bit digests for collection of keys, refer to
+    /// [XorTombstone::insert].
+    pub fn populate<T: Hash>(&mut self, keys: &[T]) {
+        keys.iter().for_each(|key| self.insert(key))
+    }
+
+    /// Populate pre-computed 64-bit digests for keys, refer to
+    /// [XorTombstone::insert].
+    pub fn populate_keys(&mut self, digests: &[u64]) {
+        self.pending.extend_from_slice(digests)
+    }
+
+    /// Build the main filter from retained keys, less the removed keys,
+    /// and inserted keys, and drop the tombstones. Keys inserted after
+    /// they were removed are part of the filter.
+    pub fn build(&mut self) {
+        let removed = std::mem::take(&mut self.removed);
+        self.keys.retain(|key| removed.binary_search(key).is_err());
+        self.keys.append(&mut self.pending);
+        self.keys.sort_unstable();
+        self.keys.dedup();
+
+        self.main
+            .build_keys_with(&self.keys, usize::MAX, DEFAULT_RNG_SEED, 1)
+            .unwrap();
+        self.tombstones = None;
+    }
+
+    /// Remove key from the filter, refer to [XorTombstone::remove_keys].
+    pub fn remove<T: ?Sized + Hash>(&mut self, key: &T) {
+        let hashed_key = self.main.hash_builder.hash_one(key);
+        self.remove_keys(&[hashed_key])
+    }
+
+    /// Remove pre-computed 64-bit digests from the filter. Digests in the
+    /// main filter are added to the tombstones, and the tombstone filter
+    /// is rebuilt. Digests inserted after the last build are dropped.
+    /// Digests unknown to the filter are ignored.
+    pub fn remove_keys(&mut self, digests: &[u64]) {
+        let mut sorted = digests.to_vec();
+        sorted.sort_unstable();
+        self.pending
+            .retain(|key| sorted.binary_search(key).is_err());
+
+        let n = self.removed.len();
+        for digest in digests.iter() {
+            // skip digests removed earlier, sorted in removed[..n].
+            let removed = self.removed[..n].binary_search(digest).is_ok();
+            if !removed && self.keys.binary_search(digest).is_ok() {
+                self.removed.push(*digest);
+            }
+        }
+        if self.removed.len() == n {
+            return;
+        }
+        self.removed.sort_unstable();
+        self.removed.dedup();
+
+        let tombstones = XorFilterBuilder::new().build_keys(&self.removed);
+        self.tombstones = Some(tombstones);
+    }
+
+    /// Check whether `key` is present in the set, refer to [XorTombstone]
+    /// for false positives and false negatives.
+    pub fn contains<T: ?Sized + Hash>(&self, key: &T) -> bool {
+        let hashed_key = self.main.hash_builder.hash_one(key);
+        self.contains_key(hashed_key)
+    }
+
+    /// Check whether pre-computed 64-bit digest is present in the set,
+    /// refer to [XorTombstone] for false positives and false negatives.
+    pub fn contains_key(&self, digest: u64) -> bool {
+        if self.keys.is_empty() || !self.main.contains_key(digest) {
+            return false;
+        }
+        match &self.tombstones {
+            Some(tombstones) => !tombstones.contains_key(digest),
+            None => true,
+        }
+    }
+
+    /// Number of keys in the main filter, less the removed keys.
+    pub fn len(&self) -> usize {
+        self.keys.len() - self.removed.len()
+    }
+
+    /// Return true if there are no keys in the filter.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of keys removed since the last build.
+    pub fn tombstones(&self) -> usize {
+        self.removed.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splitmix64;
+    use rand::prelude::random;
+
+    #[test]
+    fn test_tombstone() {
+        let mut seed: u64 = random();
+        println!("test_tombstone seed {}", seed);
+
+        let keys: Vec<u64> = (0..100_000).map(|_| splitmix64(&mut seed)).collect();
+        let (live, dead) = keys.split_at(90_000);
+
+        let mut filter = Xor8Tombstone::<BuildHasherDefault>::new();
+        assert!(!filter.contains_key(keys[0]));
+        filter.populate_keys(&keys);
+        filter.build();
+        assert_eq!(filter.len(), keys.len());
+        assert!(keys.iter().all(|key| filter.contains_key(*key)));
+
+        filter.remove_keys(&dead[..9900]);
+        dead[9900..]
+            .iter()
+            .for_each(|key| filter.remove_keys(&[*key]));
+        filter.remove_keys(&[splitmix64(&mut seed)]); // unknown key
+        assert_eq!(filter.tombstones(), dead.len());
+        assert_eq!(filter.len(), live.len());
+
+        // removing keys again doesn't rebuild the tombstones.
+        let fps = filter.tombstones.as_ref().unwrap().finger_prints().as_ptr();
+        filter.remove_keys(&dead[..10]);
+        filter.remove_keys(&[dead[0], dead[0]]);
+        assert_eq!(filter.tombstones(), dead.len());
+        let tombstones = filter.tombstones.as_ref().unwrap();
+        assert_eq!(tombstones.finger_prints().as_ptr(), fps, "rebuilt");
+
+        // removed keys are never present, few live keys are hidden.
+        assert!(dead.iter().all(|key| !filter.contains_key(*key)));
+        let fns = live
+            .iter()
+            .filter(|key| !filter.contains_key(**key))
+            .count();
+        println!("test_tombstone false negatives {} in {}", fns, live.len());
+        assert!(fns < live.len() / 100, "{} false negatives", fns);
+
+        // rebuild compacts tombstones, and false negatives are gone.
+        filter.build();
+        assert_eq!(filter.tombstones(), 0);
+        assert_eq!(filter.len(), live.len());
+        assert!(live.iter().all(|key| filter.contains_key(*key)));
+        let fps = dead.iter().filter(|key| filter.contains_key(**key)).count();
+        assert!(fps < dead.len() / 100, "{} false positives", fps);
+    }
+
+    #[test]
+    fn test_tombstone_insert() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key-{}", i)).collect();
+
+        let mut filter = Xor16Tombstone::<BuildHasherDefault>::new();
+        filter.populate(&keys[..500]);
+        filter.build();
+        filter.populate(&keys[500..]);
+        filter.remove(&keys[0]);
+        filter.remove(&keys[999]);
+        assert!(!filter.contains(&keys[0]));
+        assert!(!filter.contains(&keys[700]), "not built yet");
+        assert_eq!(filter.len(), 499);
+
+        filter.build();
+        assert_eq!(filter.len(), 998);
+        assert!(!filter.contains(&keys[0]));
+        assert!(!filter.contains(&keys[999]));
+        assert!(keys[1..999].iter().all(|key| filter.contains(key)));
+
+        // keys removed and inserted again are back after build.
+        filter.remove(&keys[1]);
+        filter.insert(&keys[1]);
+        assert!(!filter.contains(&keys[1]), "not built yet");
+        filter.build();
+        assert_eq!(filter.len(), 998);
+        assert!(keys[1..999].iter().all(|key| filter.contains(key)));
+    }
+}
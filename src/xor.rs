@@ -0,0 +1,2251 @@
+use std::{
+    borrow::Cow,
+    convert::TryInto,
+    ffi, fs,
+    hash::{BuildHasher, Hash},
+    io::{self, Read, Write},
+    marker::PhantomData,
+    sync::{Barrier, Mutex, MutexGuard},
+    thread,
+};
+
+#[allow(unused_imports)]
+use std::collections::hash_map::RandomState;
+
+use crate::hasher::{make_hasher, read_hasher, write_hasher};
+use crate::simd::{Isa, Probe};
+use crate::{
+    crc32, dedup_keys, mixsplit, prefetch, reduce, splitmix64, DEFAULT_MAX_ATTEMPTS,
+    DEFAULT_RNG_SEED,
+};
+use crate::{
+    BuildError, BuildHasherDefault, Error, Fingerprint, Hashes, KeyIndex,
+    SerializableHasher, XorSet,
+};
+
+// Fingerprints are encoded and decoded in chunks of this size, in bytes,
+// while streaming the serialized filter.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Number of keys looked up together by contains_keys_batch, fingerprints
+// for all of them are prefetched before the first comparison.
+const BATCH_SIZE: usize = 32;
+
+/// Type XorFilter is probabilistic data-structure to test membership of an
+/// element in a set.
+///
+/// XorFilter is parametrized over fingerprint type `F`, that can be
+/// [u8], [u16] or [u32], refer to [Xor8], [Xor16] and [Xor32]. Every
+/// additional bit of fingerprint halves the false positive rate, at the
+/// cost of memory.
+///
+/// XorFilter is parametrized over type `H` which is expected to implement
+/// [BuildHasher] trait, like [RandomState] and [BuildHasherDefault].
+/// When not supplied, `BuildHasherDefault` is used as the default
+/// hash-builder. To serialize and de-serialize XorFilter, hash-builder
+/// must implement [SerializableHasher], `RandomState` does not.
+///
+/// XorFilter is immutable, use [XorFilterBuilder] to build it.
+pub struct XorFilter<F, H = BuildHasherDefault>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    attempts: usize,
+    duplicates: usize,
+    pub(crate) hash_builder: H,
+    pub(crate) seed: u64,
+    pub(crate) block_length: u32,
+    pub(crate) finger_prints: Vec<F>,
+}
+
+/// XorFilter with 8-bit fingerprints.
+///
+/// This implementation has a false positive rate of about 0.3%
+/// and a memory usage of less than 9 bits per entry for sizeable sets.
+pub type Xor8<H = BuildHasherDefault> = XorFilter<u8, H>;
+
+/// XorFilter with 16-bit fingerprints.
+///
+/// This implementation has a false positive rate of about 0.0015%
+/// and a memory usage of less than 18 bits per entry for sizeable sets.
+pub type Xor16<H = BuildHasherDefault> = XorFilter<u16, H>;
+
+/// XorFilter with 32-bit fingerprints.
+///
+/// This implementation has a false positive rate of about 2.3e-8%
+/// and a memory usage of less than 36 bits per entry for sizeable sets.
+pub type Xor32<H = BuildHasherDefault> = XorFilter<u32, H>;
+
+impl<F, H> PartialEq for XorFilter<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.seed == other.seed
+            && self.block_length == other.block_length
+            && self.finger_prints == other.finger_prints
+    }
+}
+
+/// Type XorFilterBuilder collects keys and builds an [XorFilter].
+///
+/// Building consumes the builder and returns an immutable filter, hence
+/// keys can't be added to a filter after it is built, nor can a filter be
+/// queried before it is built.
+///
+/// Like [XorFilter], builder is parametrized over fingerprint type `F` and
+/// hash-builder `H`, refer to [Xor8Builder], [Xor16Builder] and
+/// [Xor32Builder].
+#[derive(Clone)]
+pub struct XorFilterBuilder<F, H = BuildHasherDefault>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    keys: Vec<u64>,
+    max_attempts: usize,
+    rng_seed: u64,
+    threads: usize,
+    hash_builder: H,
+    _fp: PhantomData<F>,
+}
+
+/// XorFilterBuilder for [Xor8].
+pub type Xor8Builder<H = BuildHasherDefault> = XorFilterBuilder<u8, H>;
+
+/// XorFilterBuilder for [Xor16].
+pub type Xor16Builder<H = BuildHasherDefault> = XorFilterBuilder<u16, H>;
+
+/// XorFilterBuilder for [Xor32].
+pub type Xor32Builder<H = BuildHasherDefault> = XorFilterBuilder<u32, H>;
+
+impl<F, H> Default for XorFilterBuilder<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher + Default,
+{
+    fn default() -> Self {
+        XorFilterBuilder::with_hasher(H::default())
+    }
+}
+
+impl<F, H> XorFilterBuilder<F, H>
+where
+    F: Fingerprint,
+    H: Default + BuildHasher,
+{
+    /// New XorFilterBuilder instance initialized with `DefaultHasher`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<F, H> XorFilterBuilder<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// New XorFilterBuilder instance initialized with supplied `hasher`.
+    pub fn with_hasher(hash_builder: H) -> Self {
+        XorFilterBuilder {
+            keys: Vec::default(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            rng_seed: DEFAULT_RNG_SEED,
+            threads: 1,
+            hash_builder,
+            _fp: PhantomData,
+        }
+    }
+
+    /// Insert 64-bit digest of a single key. Digest for the key shall
+    /// be generated using the default-hasher or via hasher supplied via
+    /// [XorFilterBuilder::with_hasher] method.
+    pub fn insert<T: ?Sized + Hash>(&mut self, key: &T) {
+        let hashed_key = self.hash_builder.hash_one(key);
+        self.keys.push(hashed_key);
+    }
+
+    /// Populate 64-bit digests for collection of keys. Digest for the key
+    /// shall be generated using the default-hasher or via hasher supplied
+    /// via [XorFilterBuilder::with_hasher] method.
+    pub fn populate<T: Hash>(&mut self, keys: &[T]) {
+        keys.iter().for_each(|key| {
+            let hashed_key = self.hash_builder.hash_one(key);
+            self.keys.push(hashed_key);
+        })
+    }
+
+    /// Populate pre-compute 64-bit digests for keys.
+    pub fn populate_keys(&mut self, keys: &[u64]) {
+        self.keys.extend_from_slice(keys)
+    }
+
+    /// Add digests collected by `other` to this builder. Both builders
+    /// shall use the same hash builder, for [XorFilter::contains] to find
+    /// keys inserted into `other`.
+    pub fn merge(&mut self, other: &Self) {
+        self.keys.extend_from_slice(&other.keys)
+    }
+
+    /// Return the digests collected so far.
+    pub fn keys(&self) -> &[u64] {
+        &self.keys
+    }
+
+    /// Set the maximum number of seeds tried by [XorFilterBuilder::try_build]
+    /// and [XorFilterBuilder::try_build_keys], defaults to
+    /// [DEFAULT_MAX_ATTEMPTS]. At least one seed is always tried, zero is
+    /// taken as 1.
+    pub fn set_max_attempts(&mut self, max_attempts: usize) -> &mut Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the initial state of the random number generator that seeds are
+    /// drawn from, defaults to [DEFAULT_RNG_SEED].
+    ///
+    /// Seeds are drawn from a splitmix64 stream, the first attempt uses
+    /// `splitmix64(rng_seed)` and every retry uses the next number in the
+    /// stream. Building the same keys from the same `rng_seed` always yields
+    /// the same filter, while different `rng_seed`s, say per shard, avoid
+    /// correlated failures. Refer to [XorFilter::attempts] for the number of
+    /// seeds tried.
+    pub fn set_seed(&mut self, rng_seed: u64) -> &mut Self {
+        self.rng_seed = rng_seed;
+        self
+    }
+
+    /// Set the number of threads used to build the filter, defaults to 1.
+    ///
+    /// With more than one thread, hashing keys into the three blocks,
+    /// collecting the initial queues of peelable slots, and peeling large
+    /// queues are spread across `threads` scoped threads, each owning a
+    /// disjoint range of slots. Queues are drained block by block, as with
+    /// a single thread, so the filter is byte-identical to the one built
+    /// with a single thread. Steps too small to gain from threads run on
+    /// the calling thread.
+    pub fn set_threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Build the filter for keys inserted using [XorFilterBuilder::insert],
+    /// [XorFilterBuilder::populate] and [XorFilterBuilder::populate_keys].
+    ///
+    /// Construction is retried with a new seed until it succeeds. If an
+    /// attempt fails, duplicate digests are detected and removed, refer
+    /// to [XorFilter::duplicates]. Use [XorFilterBuilder::try_build] to
+    /// give up after a bounded number of attempts.
+    pub fn build(mut self) -> XorFilter<F, H> {
+        let keys = std::mem::take(&mut self.keys);
+        self.build_keys_with(&keys, usize::MAX).unwrap()
+    }
+
+    /// Build the filter for pre-computed 64-bit digests for keys. Keys
+    /// inserted into the builder are ignored. Refer to
+    /// [XorFilterBuilder::build].
+    pub fn build_keys(self, keys: &[u64]) -> XorFilter<F, H> {
+        self.build_keys_with(keys, usize::MAX).unwrap()
+    }
+
+    /// Same as [XorFilterBuilder::build], but give up after
+    /// [XorFilterBuilder::set_max_attempts] attempts. Clone the builder
+    /// to retry with the same keys.
+    pub fn try_build(mut self) -> Result<XorFilter<F, H>, BuildError> {
+        let keys = std::mem::take(&mut self.keys);
+        let max_attempts = self.max_attempts;
+        self.build_keys_with(&keys, max_attempts)
+    }
+
+    /// Same as [XorFilterBuilder::build_keys], but give up after
+    /// [XorFilterBuilder::set_max_attempts] attempts.
+    pub fn try_build_keys(self, keys: &[u64]) -> Result<XorFilter<F, H>, BuildError> {
+        let max_attempts = self.max_attempts;
+        self.build_keys_with(keys, max_attempts)
+    }
+
+    fn build_keys_with(
+        self,
+        keys: &[u64],
+        max_attempts: usize,
+    ) -> Result<XorFilter<F, H>, BuildError> {
+        let mut filter = XorFilter {
+            attempts: 0,
+            duplicates: 0,
+            hash_builder: self.hash_builder,
+            seed: u64::default(),
+            block_length: u32::default(),
+            finger_prints: Vec::default(),
+        };
+        filter.build_keys_with(keys, max_attempts, self.rng_seed, self.threads)?;
+        Ok(filter)
+    }
+}
+
+impl<F, H> XorFilterBuilder<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher + Clone,
+{
+    /// Build the filter from digests collected so far, keeping them in the
+    /// builder, so that the filter can be rebuilt later with more keys.
+    /// Duplicate digests are removed from the builder.
+    pub fn rebuild(&mut self) -> XorFilter<F, H> {
+        self.keys.sort_unstable();
+        self.keys.dedup();
+        let builder = XorFilterBuilder {
+            keys: Vec::default(),
+            hash_builder: self.hash_builder.clone(),
+            ..*self
+        };
+        builder.build_keys_with(&self.keys, usize::MAX).unwrap()
+    }
+
+    /// Add pre-computed 64-bit digests to the builder, and build the filter,
+    /// refer to [XorFilterBuilder::rebuild].
+    pub fn extend_and_rebuild(&mut self, digests: &[u64]) -> XorFilter<F, H> {
+        self.populate_keys(digests);
+        self.rebuild()
+    }
+}
+
+impl<F, H> XorFilter<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// Number of seeds tried while building the filter, the last one being
+    /// [XorFilter::seed]. Zero for filters that were not built by this
+    /// process, like deserialized filters.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// Number of duplicate digests removed while building the filter.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Hash builder used to compute digests of keys.
+    pub fn hash_builder(&self) -> &H {
+        &self.hash_builder
+    }
+
+    /// Seed mixed with digests of keys, to locate their fingerprints.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of fingerprints in each of the three blocks.
+    pub fn block_length(&self) -> u32 {
+        self.block_length
+    }
+
+    /// Fingerprints of the filter, `3 * block_length` of them.
+    pub fn finger_prints(&self) -> &[F] {
+        &self.finger_prints
+    }
+
+    pub(crate) fn build_keys_with(
+        &mut self,
+        keys: &[u64],
+        max_attempts: usize,
+        rng_seed: u64,
+        threads: usize,
+    ) -> Result<(), BuildError> {
+        let (mut size, mut rngcounter) = (keys.len(), rng_seed);
+        let (mut keys, mut deduped) = (Cow::Borrowed(keys), false);
+        let mut seeds = vec![];
+        self.duplicates = 0;
+        self.attempts = 0;
+        let capacity = {
+            let capacity = 32 + ((1.23 * (size as f64)).ceil() as u32);
+            capacity / 3 * 3 // round it down to a multiple of 3
+        };
+        self.seed = splitmix64(&mut rngcounter);
+        self.block_length = capacity / 3;
+        self.finger_prints = vec![Default::default(); capacity as usize];
+
+        let block_length = self.block_length as usize;
+        let mut q0: Vec<KeyIndex> = Vec::with_capacity(block_length);
+        let mut q1: Vec<KeyIndex> = Vec::with_capacity(block_length);
+        let mut q2: Vec<KeyIndex> = Vec::with_capacity(block_length);
+        let mut stack: Vec<KeyIndex> = Vec::with_capacity(size);
+        let mut sets0: Vec<XorSet> = vec![Default::default(); block_length];
+        let mut sets1: Vec<XorSet> = vec![Default::default(); block_length];
+        let mut sets2: Vec<XorSet> = vec![Default::default(); block_length];
+
+        loop {
+            seeds.push(self.seed);
+            if threads > 1 && keys.len() >= PARALLEL_SIZE {
+                let sets = [&mut sets0[..], &mut sets1[..], &mut sets2[..]];
+                count_parallel(&keys, self.seed, self.block_length, sets, threads);
+            } else {
+                for key in keys.iter() {
+                    let hs = self.geth0h1h2(*key);
+                    sets0[hs.h0 as usize].xor_mask ^= hs.h;
+                    sets0[hs.h0 as usize].count += 1;
+                    sets1[hs.h1 as usize].xor_mask ^= hs.h;
+                    sets1[hs.h1 as usize].count += 1;
+                    sets2[hs.h2 as usize].xor_mask ^= hs.h;
+                    sets2[hs.h2 as usize].count += 1;
+                }
+            }
+
+            fill_queue(&sets0, &mut q0, threads);
+            fill_queue(&sets1, &mut q1, threads);
+            fill_queue(&sets2, &mut q2, threads);
+
+            stack.clear();
+
+            if threads > 1 {
+                let sets = [&mut sets0[..], &mut sets1[..], &mut sets2[..]];
+                let qs = [&mut q0, &mut q1, &mut q2];
+                peel_parallel(sets, qs, self.block_length, threads, &mut stack);
+            } else {
+                while !q0.is_empty() || !q1.is_empty() || !q2.is_empty() {
+                    while let Some(keyindexvar) = q0.pop() {
+                        if sets0[keyindexvar.index as usize].count == 0 {
+                            // not actually possible after the initial scan.
+                            continue;
+                        }
+                        let hash = keyindexvar.hash;
+                        let h1 = self.geth1(hash);
+                        let h2 = self.geth2(hash);
+                        stack.push(keyindexvar);
+
+                        let s = unsafe { sets1.get_unchecked_mut(h1 as usize) };
+                        s.xor_mask ^= hash;
+                        s.count -= 1;
+                        if s.count == 1 {
+                            q1.push(KeyIndex {
+                                index: h1,
+                                hash: s.xor_mask,
+                            })
+                        }
+
+                        let s = unsafe { sets2.get_unchecked_mut(h2 as usize) };
+                        s.xor_mask ^= hash;
+                        s.count -= 1;
+                        if s.count == 1 {
+                            q2.push(KeyIndex {
+                                index: h2,
+                                hash: s.xor_mask,
+                            })
+                        }
+                    }
+                    while let Some(mut keyindexvar) = q1.pop() {
+                        if sets1[keyindexvar.index as usize].count == 0 {
+                            continue;
+                        }
+                        let hash = keyindexvar.hash;
+                        let h0 = self.geth0(hash);
+                        let h2 = self.geth2(hash);
+                        keyindexvar.index += self.block_length;
+                        stack.push(keyindexvar);
+
+                        let s = unsafe { sets0.get_unchecked_mut(h0 as usize) };
+                        s.xor_mask ^= hash;
+                        s.count -= 1;
+                        if s.count == 1 {
+                            q0.push(KeyIndex {
+                                index: h0,
+                                hash: s.xor_mask,
+                            })
+                        }
+
+                        let s = unsafe { sets2.get_unchecked_mut(h2 as usize) };
+                        s.xor_mask ^= hash;
+                        s.count -= 1;
+                        if s.count == 1 {
+                            q2.push(KeyIndex {
+                                index: h2,
+                                hash: s.xor_mask,
+                            })
+                        }
+                    }
+                    while let Some(mut keyindexvar) = q2.pop() {
+                        if sets2[keyindexvar.index as usize].count == 0 {
+                            continue;
+                        }
+                        let hash = keyindexvar.hash;
+                        let h0 = self.geth0(hash);
+                        let h1 = self.geth1(hash);
+                        keyindexvar.index += 2 * self.block_length;
+                        stack.push(keyindexvar);
+
+                        let s = unsafe { sets0.get_unchecked_mut(h0 as usize) };
+                        s.xor_mask ^= hash;
+                        s.count -= 1;
+                        if s.count == 1 {
+                            q0.push(KeyIndex {
+                                index: h0,
+                                hash: s.xor_mask,
+                            })
+                        }
+                        let s = unsafe { sets1.get_unchecked_mut(h1 as usize) };
+                        s.xor_mask ^= hash;
+                        s.count -= 1;
+                        if s.count == 1 {
+                            q1.push(KeyIndex {
+                                index: h1,
+                                hash: s.xor_mask,
+                            })
+                        }
+                    }
+                }
+            }
+
+            if stack.len() == size {
+                self.attempts = seeds.len();
+                break;
+            } else if seeds.len() >= max_attempts {
+                let unpeeled = size - stack.len();
+                return Err(BuildError { unpeeled, seeds });
+            }
+
+            // peeling can never succeed with duplicate digests.
+            if !deduped {
+                if let Some(uniq) = dedup_keys(&keys) {
+                    self.duplicates = size - uniq.len();
+                    size = uniq.len();
+                    keys = Cow::Owned(uniq);
+                }
+                deduped = true;
+            }
+
+            for item in sets0.iter_mut() {
+                *item = Default::default();
+            }
+            for item in sets1.iter_mut() {
+                *item = Default::default();
+            }
+            for item in sets2.iter_mut() {
+                *item = Default::default();
+            }
+            self.seed = splitmix64(&mut rngcounter)
+        }
+
+        while let Some(ki) = stack.pop() {
+            let mut val = F::from_hash(ki.hash);
+            if ki.index < self.block_length {
+                let h1 = (self.geth1(ki.hash) + self.block_length) as usize;
+                let h2 = (self.geth2(ki.hash) + 2 * self.block_length) as usize;
+                val ^= self.finger_prints[h1] ^ self.finger_prints[h2];
+            } else if ki.index < 2 * self.block_length {
+                let h0 = self.geth0(ki.hash) as usize;
+                let h2 = (self.geth2(ki.hash) + 2 * self.block_length) as usize;
+                val ^= self.finger_prints[h0] ^ self.finger_prints[h2];
+            } else {
+                let h0 = self.geth0(ki.hash) as usize;
+                let h1 = (self.geth1(ki.hash) + self.block_length) as usize;
+                val ^= self.finger_prints[h0] ^ self.finger_prints[h1]
+            }
+            self.finger_prints[ki.index as usize] = val;
+        }
+
+        Ok(())
+    }
+
+    /// Contains tell you whether the key is likely part of the set.
+    pub fn contains<T: ?Sized + Hash>(&self, key: &T) -> bool {
+        let hashed_key = self.hash_builder.hash_one(key);
+        self.contains_key(hashed_key)
+    }
+
+    pub fn contains_key(&self, key: u64) -> bool {
+        let hash = mixsplit(key, self.seed);
+        let f = F::from_hash(hash);
+        let [h0, h1, h2] = positions(hash, self.block_length);
+        f == (self.finger_prints[h0] ^ self.finger_prints[h1] ^ self.finger_prints[h2])
+    }
+
+    /// Check membership of pre-computed digests, like
+    /// [XorFilter::contains_key], for a batch of `keys`, setting
+    /// `result[i]` for `keys[i]`.
+    ///
+    /// Positions of the fingerprints are computed and prefetched for
+    /// several keys at a time before comparing them, overlapping the cache
+    /// misses. Faster than looking up each key when the filter does not
+    /// fit in cache. Keys are hashed with AVX2 or SSE4.1 instructions when
+    /// the CPU supports them, with the same result as [XorFilter::contains_key].
+    ///
+    /// Panics if `keys` and `result` differ in length.
+    pub fn contains_keys_batch(&self, keys: &[u64], result: &mut [bool]) {
+        assert_eq!(keys.len(), result.len(), "keys and result differ in length");
+
+        let fps = &self.finger_prints;
+        let isa = Isa::detect();
+        let mut batch: [Probe; BATCH_SIZE] = [(0, [0; 3]); BATCH_SIZE];
+        for (keys, result) in keys.chunks(BATCH_SIZE).zip(result.chunks_mut(BATCH_SIZE)) {
+            isa.probes(keys, self.seed, self.block_length, &mut batch);
+            for (_, hs) in batch[..keys.len()].iter() {
+                hs.iter().for_each(|h| prefetch(&fps[*h]));
+            }
+            for ((hash, [h0, h1, h2]), res) in batch.iter().zip(result.iter_mut()) {
+                *res = F::from_hash(*hash) == (fps[*h0] ^ fps[*h1] ^ fps[*h2]);
+            }
+        }
+    }
+
+    fn geth0h1h2(&self, k: u64) -> Hashes {
+        let h = mixsplit(k, self.seed);
+        Hashes {
+            h,
+            h0: reduce(h as u32, self.block_length),
+            h1: reduce(h.rotate_left(21) as u32, self.block_length),
+            h2: reduce(h.rotate_left(42) as u32, self.block_length),
+        }
+    }
+
+    fn geth0(&self, hash: u64) -> u32 {
+        let r0 = hash as u32;
+        reduce(r0, self.block_length)
+    }
+
+    fn geth1(&self, hash: u64) -> u32 {
+        let r1 = hash.rotate_left(21) as u32;
+        reduce(r1, self.block_length)
+    }
+
+    fn geth2(&self, hash: u64) -> u32 {
+        let r2 = hash.rotate_left(42) as u32;
+        reduce(r2, self.block_length)
+    }
+}
+
+impl<F, H> XorFilter<F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// File signature write on first 4 bytes of file.
+    /// ^ stands for xor
+    /// TL stands for filter with 1-byte fingerprints, T2 and T4 for
+    /// filters with 2-byte and 4-byte fingerprints
+    /// 1 stands for version 1
+    pub(crate) const SIGNATURE_V1: [u8; 4] = match F::SIZE {
+        1 => [b'^', b'T', b'L', 1],
+        2 => [b'^', b'T', b'2', 1],
+        _ => [b'^', b'T', b'4', 1],
+    };
+
+    /// Same as SIGNATURE_V1, 2 stands for version 2. Version 2 adds
+    /// checksum of the header and fingerprints.
+    pub(crate) const SIGNATURE_V2: [u8; 4] = {
+        let sig = Self::SIGNATURE_V1;
+        [sig[0], sig[1], sig[2], 2]
+    };
+
+    /// METADATA_LENGTH is size that required to write size of all the
+    /// metadata of the serialized filter.
+    // signature length + seed length + block-length +
+    //      fingerprint count
+    pub(crate) const METADATA_LENGTH_V1: usize = 4 + 8 + 4 + 4;
+    // version 1 metadata + checksum, followed by hasher's identity and
+    // parameters.
+    pub(crate) const METADATA_LENGTH_V2: usize = Self::METADATA_LENGTH_V1 + 4;
+
+    /// Write to file in binary format
+    pub fn write_file(&self, path: &ffi::OsStr) -> Result<usize, Error>
+    where
+        H: SerializableHasher,
+    {
+        let mut f = io::BufWriter::new(fs::File::create(path)?);
+        let n = self.write_to(&mut f)?;
+        f.flush()?;
+        Ok(n)
+    }
+
+    /// Read from file in binary format
+    pub fn read_file(path: &ffi::OsStr) -> Result<Self, Error>
+    where
+        H: SerializableHasher,
+    {
+        let mut f = io::BufReader::new(fs::File::open(path)?);
+        let filter = Self::read_from(&mut f)?;
+        match io::copy(&mut f, &mut io::sink())? {
+            0 => Ok(filter),
+            n => Err(Error::TrailingBytes(n as usize)),
+        }
+    }
+
+    /// Serialize in version 2 format. Fingerprints are encoded in
+    /// big-endian, the fingerprint-count in the header is the number of
+    /// fingerprints. Header is followed by CRC-32 checksum of the
+    /// serialized filter, and by the identity and parameters of the
+    /// hash builder.
+    pub fn to_bytes(&self) -> Vec<u8>
+    where
+        H: SerializableHasher,
+    {
+        let n = Self::METADATA_LENGTH_V2 + (self.finger_prints.len() * F::SIZE);
+        let mut buf: Vec<u8> = Vec::with_capacity(n);
+        self.write_to(&mut buf).unwrap(); // writing to Vec<u8> cannot fail
+        buf
+    }
+
+    /// Serialize in version 2 format, same as [XorFilter::to_bytes], into
+    /// `w`. Fingerprints are encoded and written in chunks, without
+    /// buffering the serialized filter. Return the number of bytes
+    /// written.
+    pub fn write_to<W>(&self, mut w: W) -> Result<usize, Error>
+    where
+        W: Write,
+        H: SerializableHasher,
+    {
+        let mut hdr: Vec<u8> = Vec::with_capacity(Self::METADATA_LENGTH_V2 + 32);
+        hdr.extend_from_slice(&Self::SIGNATURE_V2);
+        hdr.extend_from_slice(&self.seed.to_be_bytes());
+        hdr.extend_from_slice(&self.block_length.to_be_bytes());
+        hdr.extend_from_slice(&(self.finger_prints.len() as u32).to_be_bytes());
+        hdr.extend_from_slice(&[0; 4]);
+        write_hasher(&self.hash_builder, &mut hdr);
+
+        // checksum precedes the fingerprints, encode them twice instead
+        // of buffering.
+        let (m1, m2) = (Self::METADATA_LENGTH_V1, Self::METADATA_LENGTH_V2);
+        let mut checksum = crc32(crc32(0, &hdr[..m1]), &hdr[m2..]);
+        let mut chunk: Vec<u8> = Vec::with_capacity(CHUNK_SIZE);
+        for fps in self.finger_prints.chunks(CHUNK_SIZE / F::SIZE) {
+            chunk.clear();
+            F::encode(fps, &mut chunk);
+            checksum = crc32(checksum, &chunk);
+        }
+        hdr[m1..m2].copy_from_slice(&checksum.to_be_bytes());
+
+        w.write_all(&hdr)?;
+        for fps in self.finger_prints.chunks(CHUNK_SIZE / F::SIZE) {
+            chunk.clear();
+            F::encode(fps, &mut chunk);
+            w.write_all(&chunk)?;
+        }
+        Ok(hdr.len() + (self.finger_prints.len() * F::SIZE))
+    }
+
+    /// Deserialize from version 1 or version 2 format, same as
+    /// [XorFilter::from_bytes], reading from `r`. Fingerprints are read and
+    /// decoded in chunks, reading stops at the end of the serialized
+    /// filter.
+    pub fn read_from<R>(mut r: R) -> Result<Self, Error>
+    where
+        R: Read,
+        H: SerializableHasher,
+    {
+        // header length depends on the version.
+        let mut buf = vec![0; Self::METADATA_LENGTH_V1];
+        read_full(&mut r, &mut buf[..4])?;
+        if buf[..3] != Self::SIGNATURE_V1[..3] {
+            return Err(Error::InvalidSignature(buf[..3].try_into().unwrap()));
+        }
+        match buf[3] {
+            1 => (),
+            2 => buf.resize(Self::METADATA_LENGTH_V2 + 8, 0),
+            ver => return Err(Error::UnsupportedVersion(ver)),
+        }
+        read_full(&mut r, &mut buf[4..])?;
+        if buf[3] == 2 {
+            let off = buf.len() - 4;
+            let n = u32::from_be_bytes(buf[off..].try_into().unwrap()) as usize;
+            let m = r.by_ref().take(n as u64).read_to_end(&mut buf)?;
+            if m < n {
+                return Err(Error::Truncated {
+                    expected: n,
+                    actual: m,
+                });
+            }
+        }
+
+        let hdr = Self::read_header(&buf)?;
+        let n = hdr.validate_layout(F::SIZE)?;
+        let hash_builder = hdr.hash_builder()?;
+
+        let mut checksum = crc32(crc32(0, &buf[..20]), buf.get(24..).unwrap_or(&[]));
+        let mut finger_prints: Vec<F> = Vec::with_capacity(hdr.fp_len.min(CHUNK_SIZE));
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let mut done = 0;
+        while done < n {
+            let m = (n - done).min(CHUNK_SIZE);
+            read_full(&mut r, &mut chunk[..m]).map_err(|err| match err {
+                Error::Truncated { actual, .. } => Error::Truncated {
+                    expected: n,
+                    actual: done + actual,
+                },
+                err => err,
+            })?;
+            checksum = crc32(checksum, &chunk[..m]);
+            finger_prints.extend(F::decode(&chunk[..m]));
+            done += m;
+        }
+        match hdr.checksum {
+            Some(expected) if expected != checksum => Err(Error::ChecksumMismatch {
+                expected,
+                actual: checksum,
+            }),
+            _ => Ok(Self::from_parts(
+                hash_builder,
+                hdr.seed,
+                hdr.block_length,
+                finger_prints,
+            )),
+        }
+    }
+
+    /// Deserialize from version 1 or version 2 format. Fails with
+    /// [Error::UnsupportedVersion] for unknown versions, and with
+    /// [Error::HasherMismatch] if filter was serialized with another type
+    /// of hash builder, refer to [Error::is_corrupt] for corrupt data.
+    /// `buf` must hold exactly one serialized filter, with
+    /// `3 * block_length` fingerprints.
+    ///
+    /// Version 1 format does not carry the hash builder, it is created
+    /// from empty parameters.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self, Error>
+    where
+        H: SerializableHasher,
+    {
+        // validate the buf first.
+        let hdr = Self::read_header(&buf)?;
+        let n = hdr.validate(&buf, F::SIZE)?;
+        if buf.len() > (hdr.offset + n) {
+            return Err(Error::TrailingBytes(buf.len() - hdr.offset - n));
+        }
+        hdr.verify(&buf, n)?;
+        let hash_builder = hdr.hash_builder()?;
+        let finger_prints = F::decode(&buf[hdr.offset..]);
+        Ok(Self::from_parts(
+            hash_builder,
+            hdr.seed,
+            hdr.block_length,
+            finger_prints,
+        ))
+    }
+
+    // Create a built filter from its deserialized parts, that are expected
+    // to be validated by the caller.
+    pub(crate) fn from_parts(
+        hash_builder: H,
+        seed: u64,
+        block_length: u32,
+        finger_prints: Vec<F>,
+    ) -> Self {
+        XorFilter {
+            attempts: 0,
+            duplicates: 0,
+            hash_builder,
+            seed,
+            block_length,
+            finger_prints,
+        }
+    }
+
+    // Read the signature and the metadata, from version 1 or version 2
+    // format. Fingerprints are not validated.
+    pub(crate) fn read_header(buf: &[u8]) -> Result<Header<'_>, Error> {
+        if buf.len() < 4 {
+            return Err(Error::Truncated {
+                expected: 4,
+                actual: buf.len(),
+            });
+        }
+        if buf[..3] != Self::SIGNATURE_V1[..3] {
+            return Err(Error::InvalidSignature(buf[..3].try_into().unwrap()));
+        }
+        let offset = match buf[3] {
+            1 => Self::METADATA_LENGTH_V1,
+            2 => Self::METADATA_LENGTH_V2,
+            ver => return Err(Error::UnsupportedVersion(ver)),
+        };
+        if offset > buf.len() {
+            return Err(Error::Truncated {
+                expected: offset,
+                actual: buf.len(),
+            });
+        }
+        let (checksum, hasher, offset) = match buf[3] {
+            1 => (None, None, offset),
+            _ => {
+                let checksum = u32::from_be_bytes(buf[20..24].try_into().unwrap());
+                let (id, params, n) = read_hasher(&buf[offset..])?;
+                (Some(checksum), Some((id, params)), offset + n)
+            }
+        };
+        Ok(Header {
+            seed: u64::from_be_bytes(buf[4..12].try_into().unwrap()),
+            block_length: u32::from_be_bytes(buf[12..16].try_into().unwrap()),
+            fp_len: u32::from_be_bytes(buf[16..20].try_into().unwrap()) as usize,
+            checksum,
+            hasher,
+            offset,
+        })
+    }
+}
+
+// Metadata of serialized XorFilter.
+pub(crate) struct Header<'a> {
+    pub(crate) seed: u64,
+    pub(crate) block_length: u32,
+    pub(crate) fp_len: usize,
+    // from version 2 onwards.
+    pub(crate) checksum: Option<u32>,
+    // from version 2 onwards, identity and parameters of hash builder.
+    pub(crate) hasher: Option<([u8; 4], &'a [u8])>,
+    // fingerprints start from this offset.
+    pub(crate) offset: usize,
+}
+
+impl<'a> Header<'a> {
+    // Validate metadata against serialized `buf`, for fingerprints of
+    // `size` bytes. Return the length of fingerprints in bytes.
+    pub(crate) fn validate(&self, buf: &[u8], size: usize) -> Result<usize, Error> {
+        let n = self.validate_layout(size)?;
+        if n > (buf.len() - self.offset) {
+            let actual = buf.len() - self.offset;
+            return Err(Error::Truncated {
+                expected: n,
+                actual,
+            });
+        }
+        Ok(n)
+    }
+
+    // Validate metadata, for fingerprints of `size` bytes. Return the
+    // length of fingerprints in bytes.
+    pub(crate) fn validate_layout(&self, size: usize) -> Result<usize, Error> {
+        let (block_length, fp_len) = (self.block_length, self.fp_len);
+        if block_length == 0 {
+            return Err(Error::ZeroBlockLength);
+        }
+        if (block_length as u64) * 3 != (fp_len as u64) {
+            return Err(Error::BlockLengthMismatch {
+                block_length,
+                fp_len,
+            });
+        }
+        fp_len.checked_mul(size).ok_or(Error::Overflow)
+    }
+
+    // Match checksum against serialized `buf`, that must hold `n` bytes
+    // of fingerprints after the header.
+    pub(crate) fn verify(&self, buf: &[u8], n: usize) -> Result<(), Error> {
+        match self.checksum {
+            Some(expected) => {
+                let rest = &buf[24..(self.offset + n)];
+                match crc32(crc32(0, &buf[..20]), rest) {
+                    actual if actual != expected => {
+                        Err(Error::ChecksumMismatch { expected, actual })
+                    }
+                    _ => Ok(()),
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Create hash builder of serialized filter.
+    pub(crate) fn hash_builder<H>(&self) -> Result<H, Error>
+    where
+        H: SerializableHasher,
+    {
+        match self.hasher {
+            Some((id, params)) => make_hasher(id, params),
+            None => H::from_params(&[]),
+        }
+    }
+}
+
+/// Type XorFilterRef is a read-only view of [XorFilter], serialized by
+/// [XorFilter::to_bytes], borrowing the fingerprints from the serialized
+/// bytes in place.
+///
+/// Useful for filters that are stored inside a larger buffer, like a
+/// memory-mapped file, queries never copy or allocate. Bytes following the
+/// serialized filter are ignored.
+pub struct XorFilterRef<'a, F, H = BuildHasherDefault>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    pub(crate) hash_builder: H,
+    pub(crate) seed: u64,
+    pub(crate) block_length: u32,
+    finger_prints: &'a [u8],
+    _fp: PhantomData<F>,
+}
+
+/// Read-only view of serialized [Xor8].
+pub type Xor8Ref<'a, H = BuildHasherDefault> = XorFilterRef<'a, u8, H>;
+
+/// Read-only view of serialized [Xor16].
+pub type Xor16Ref<'a, H = BuildHasherDefault> = XorFilterRef<'a, u16, H>;
+
+/// Read-only view of serialized [Xor32].
+pub type Xor32Ref<'a, H = BuildHasherDefault> = XorFilterRef<'a, u32, H>;
+
+impl<'a, F, H> XorFilterRef<'a, F, H>
+where
+    F: Fingerprint,
+    H: BuildHasher,
+{
+    /// Validate the header in `buf`, and the checksum for version 2
+    /// format, and borrow the fingerprints that follow.
+    pub fn from_bytes(buf: &'a [u8]) -> Result<Self, Error>
+    where
+        H: SerializableHasher,
+    {
+        Self::parse(buf, true)
+    }
+
+    // Same as from_bytes, checksum is matched only if `verify` is true.
+    pub(crate) fn parse(buf: &'a [u8], verify: bool) -> Result<Self, Error>
+    where
+        H: SerializableHasher,
+    {
+        let hdr = XorFilter::<F, H>::read_header(buf)?;
+        let n = hdr.validate(buf, F::SIZE)?;
+        if verify {
+            hdr.verify(buf, n)?;
+        }
+        Ok(XorFilterRef {
+            hash_builder: hdr.hash_builder()?,
+            seed: hdr.seed,
+            block_length: hdr.block_length,
+            finger_prints: &buf[hdr.offset..(hdr.offset + n)],
+            _fp: PhantomData,
+        })
+    }
+
+    /// Hash builder used to compute digests of keys.
+    pub fn hash_builder(&self) -> &H {
+        &self.hash_builder
+    }
+
+    /// Seed mixed with digests of keys, to locate their fingerprints.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of fingerprints in each of the three blocks.
+    pub fn block_length(&self) -> u32 {
+        self.block_length
+    }
+
+    /// Contains tell you whether the key is likely part of the set.
+    pub fn contains<T: ?Sized + Hash>(&self, key: &T) -> bool {
+        let hashed_key = self.hash_builder.hash_one(key);
+        self.contains_key(hashed_key)
+    }
+
+    pub fn contains_key(&self, key: u64) -> bool {
+        let hash = mixsplit(key, self.seed);
+        let f = F::from_hash(hash);
+        let [h0, h1, h2] = positions(hash, self.block_length);
+        let fps = self.finger_prints;
+        f == (F::decode_at(fps, h0) ^ F::decode_at(fps, h1) ^ F::decode_at(fps, h2))
+    }
+}
+
+// Number of keys, slots in a block, or slots in a queue, below which they
+// are hashed, scanned, or peeled by the calling thread.
+const PARALLEL_SIZE: usize = 1 << 14;
+
+// Number of keys hashed by each thread in a round of count_parallel.
+const ROUND_SIZE: usize = 1 << 18;
+
+// Hash `keys` into the three blocks of `sets`, using `threads` threads.
+// Slots are partitioned into `threads` ranges, and each thread owns a range
+// in all three blocks. In every round, each thread hashes a slice of keys,
+// into per-partition buckets, then applies the buckets of its partition.
+// XOR and count are commutative, sets are the same as if computed serially.
+fn count_parallel(
+    keys: &[u64],
+    seed: u64,
+    block_length: u32,
+    sets: [&mut [XorSet]; 3],
+    threads: usize,
+) {
+    // index of hash's slot in `block`.
+    let slot = |hash: u64, block: usize| -> usize {
+        let r = hash.rotate_left(21 * (block as u32)) as u32;
+        reduce(r, block_length) as usize
+    };
+
+    let part = (block_length as usize).div_ceil(threads);
+    let nbuckets = 3 * threads; // per thread, `threads` buckets for each block.
+    let buckets: Vec<Mutex<Vec<u64>>> = (0..(threads * nbuckets))
+        .map(|_| Mutex::default())
+        .collect();
+    let barrier = Barrier::new(threads);
+
+    let [sets0, sets1, sets2] = sets;
+    let (mut parts0, mut parts1) = (sets0.chunks_mut(part), sets1.chunks_mut(part));
+    let mut parts2 = sets2.chunks_mut(part);
+
+    thread::scope(|s| {
+        for w in 0..threads {
+            let mut parts = [parts0.next(), parts1.next(), parts2.next()];
+            let (buckets, barrier) = (&buckets, &barrier);
+            s.spawn(move || {
+                for round in keys.chunks(threads * ROUND_SIZE) {
+                    let n = round.len().div_ceil(threads);
+                    {
+                        let mut bs: Vec<MutexGuard<Vec<u64>>> = buckets
+                            [(w * nbuckets)..((w + 1) * nbuckets)]
+                            .iter()
+                            .map(|b| b.lock().unwrap())
+                            .collect();
+                        for key in round.chunks(n).nth(w).unwrap_or(&[]).iter() {
+                            let hash = mixsplit(*key, seed);
+                            for block in 0..3 {
+                                let p = slot(hash, block) / part;
+                                bs[(block * threads) + p].push(hash);
+                            }
+                        }
+                    }
+                    barrier.wait();
+
+                    let off = w * part;
+                    for (block, sets) in parts.iter_mut().enumerate() {
+                        let sets = match sets {
+                            Some(sets) => sets,
+                            None => continue,
+                        };
+                        for t in 0..threads {
+                            let b = (t * nbuckets) + (block * threads) + w;
+                            for hash in buckets[b].lock().unwrap().drain(..) {
+                                let s = &mut sets[slot(hash, block) - off];
+                                s.xor_mask ^= hash;
+                                s.count += 1;
+                            }
+                        }
+                    }
+                    barrier.wait();
+                }
+            });
+        }
+    });
+}
+
+// Collect slots of `sets`, in block, with a single key, into `q`. Slots are
+// scanned in ranges by `threads` threads, and collected in the order of
+// slots.
+fn fill_queue(sets: &[XorSet], q: &mut Vec<KeyIndex>, threads: usize) {
+    let collect = |off: usize, sets: &[XorSet], q: &mut Vec<KeyIndex>| {
+        for (i, item) in sets.iter().enumerate() {
+            if item.count == 1 {
+                q.push(KeyIndex {
+                    index: (off + i) as u32,
+                    hash: item.xor_mask,
+                });
+            }
+        }
+    };
+
+    q.clear();
+    if threads <= 1 || sets.len() < PARALLEL_SIZE {
+        collect(0, sets, q);
+        return;
+    }
+
+    let part = sets.len().div_ceil(threads);
+    thread::scope(|s| {
+        let handles: Vec<_> = sets
+            .chunks(part)
+            .enumerate()
+            .map(|(i, sets)| {
+                s.spawn(move || {
+                    let mut q = vec![];
+                    collect(i * part, sets, &mut q);
+                    q
+                })
+            })
+            .collect();
+        for handle in handles.into_iter() {
+            q.extend(handle.join().unwrap());
+        }
+    });
+}
+
+// Peel keys off the three blocks of `sets` into `stack`, starting from the
+// queues `qs` of slots, in each block, with a single key.
+//
+// Like the serial peel in build_keys_with, queue of block 0 is drained, then
+// queue of block 1, then queue of block 2, until all three are empty. While
+// a block's queue is drained, keys are removed only from the other two
+// blocks, so which keys are peeled from that block, and from which slots,
+// doesn't depend on the order the queue is drained in. Fingerprints assigned
+// from `stack` only depend on that, hence the filter is byte-identical to the
+// one peeled serially. Queues larger than PARALLEL_SIZE are scanned by
+// `threads` threads, and peeled keys are removed by `threads` threads, each
+// owning a range of slots in the other two blocks, like count_parallel.
+fn peel_parallel(
+    mut sets: [&mut [XorSet]; 3],
+    mut qs: [&mut Vec<KeyIndex>; 3],
+    block_length: u32,
+    threads: usize,
+    stack: &mut Vec<KeyIndex>,
+) {
+    let bl = block_length as usize;
+    let part = bl.div_ceil(threads);
+    // index of hash's slot in `block`.
+    let slot = |hash: u64, block: usize| -> usize {
+        let r = hash.rotate_left(21 * (block as u32)) as u32;
+        reduce(r, block_length) as usize
+    };
+
+    while qs.iter().any(|q| !q.is_empty()) {
+        for block in 0..3 {
+            let (q, oqs) = split_blocks(&mut qs, block);
+            let (sets, others) = split_blocks(&mut sets, block);
+            let obs = [(block + 1) % 3, (block + 2) % 3];
+            let [osets0, osets1] = others;
+
+            if q.len() < PARALLEL_SIZE {
+                for ki in q.iter() {
+                    if sets[ki.index as usize].count == 0 {
+                        continue;
+                    }
+                    stack.push(KeyIndex {
+                        index: ki.index + ((block * bl) as u32),
+                        hash: ki.hash,
+                    });
+                    let index = slot(ki.hash, obs[0]);
+                    remove_key(osets0, 0, index, ki.hash, oqs[0]);
+                    let index = slot(ki.hash, obs[1]);
+                    remove_key(osets1, 0, index, ki.hash, oqs[1]);
+                }
+                q.clear();
+                continue;
+            }
+
+            // peeled keys, and their hashes bucketed by the other block and
+            // by the range of slots in that block.
+            let scans: Vec<(Vec<KeyIndex>, Vec<Vec<u64>>)> = thread::scope(|s| {
+                let sets = &**sets;
+                let handles: Vec<_> = q
+                    .chunks(q.len().div_ceil(threads))
+                    .map(|q| {
+                        s.spawn(move || {
+                            let (mut peeled, mut removes) =
+                                (vec![], vec![vec![]; 2 * threads]);
+                            for ki in q.iter() {
+                                if sets[ki.index as usize].count == 0 {
+                                    continue;
+                                }
+                                peeled.push(KeyIndex {
+                                    index: ki.index + ((block * bl) as u32),
+                                    hash: ki.hash,
+                                });
+                                for (i, ob) in obs.iter().enumerate() {
+                                    let p = slot(ki.hash, *ob) / part;
+                                    removes[(i * threads) + p].push(ki.hash);
+                                }
+                            }
+                            (peeled, removes)
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+            scans
+                .iter()
+                .for_each(|(peeled, _)| stack.extend_from_slice(peeled));
+            q.clear();
+
+            thread::scope(|s| {
+                let (mut parts0, mut parts1) =
+                    (osets0.chunks_mut(part), osets1.chunks_mut(part));
+                let handles: Vec<_> = (0..threads)
+                    .map(|w| {
+                        let mut parts = [parts0.next(), parts1.next()];
+                        let scans = &scans;
+                        s.spawn(move || {
+                            let mut nqs = [vec![], vec![]];
+                            for (i, sets) in parts.iter_mut().enumerate() {
+                                let sets = match sets {
+                                    Some(sets) => sets,
+                                    None => continue,
+                                };
+                                let off = w * part;
+                                for (_, removes) in scans.iter() {
+                                    for hash in removes[(i * threads) + w].iter() {
+                                        let index = slot(*hash, obs[i]);
+                                        remove_key(sets, off, index, *hash, &mut nqs[i]);
+                                    }
+                                }
+                            }
+                            nqs
+                        })
+                    })
+                    .collect();
+                for handle in handles.into_iter() {
+                    let [nq0, nq1] = handle.join().unwrap();
+                    oqs[0].extend(nq0);
+                    oqs[1].extend(nq1);
+                }
+            });
+        }
+    }
+}
+
+// Remove key's `hash` from slot `index` of a block, in `sets`, a range of
+// slots in that block starting from `off`, and collect the slot into `q`
+// if it is left with a single key.
+#[inline]
+fn remove_key(
+    sets: &mut [XorSet],
+    off: usize,
+    index: usize,
+    hash: u64,
+    q: &mut Vec<KeyIndex>,
+) {
+    let s = &mut sets[index - off];
+    s.xor_mask ^= hash;
+    s.count -= 1;
+    if s.count == 1 {
+        q.push(KeyIndex {
+            index: index as u32,
+            hash: s.xor_mask,
+        })
+    }
+}
+
+// Split `items` into the one for `block`, and the ones for the other two
+// blocks, in the order of block + 1 and block + 2, modulo 3.
+fn split_blocks<T>(items: &mut [T; 3], block: usize) -> (&mut T, [&mut T; 2]) {
+    let [item0, item1, item2] = items;
+    match block {
+        0 => (item0, [item1, item2]),
+        1 => (item1, [item2, item0]),
+        _ => (item2, [item0, item1]),
+    }
+}
+
+// fill `buf` from `r`, fail with Error::Truncated if `r` ends before that.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => {
+                return Err(Error::Truncated {
+                    expected: buf.len(),
+                    actual: n,
+                })
+            }
+            Ok(m) => n += m,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+// index of key's fingerprints, from its `hash`, in each of the three blocks.
+#[inline]
+pub(crate) fn positions(hash: u64, block_length: u32) -> [usize; 3] {
+    let r0 = hash as u32;
+    let r1 = hash.rotate_left(21) as u32;
+    let r2 = hash.rotate_left(42) as u32;
+    let h0 = reduce(r0, block_length) as usize;
+    let h1 = (reduce(r1, block_length) + block_length) as usize;
+    let h2 = (reduce(r2, block_length) + 2 * block_length) as usize;
+    [h0, h1, h2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{prelude::random, rngs::SmallRng, Rng, SeedableRng};
+    use std::io;
+
+    #[test]
+    fn test_basic1() {
+        let seed: u128 = random();
+        println!("test_basic1 seed {}", seed);
+        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+        let testsize = 1_000_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = rng.gen();
+        }
+
+        let filter = {
+            let mut builder = Xor8Builder::<RandomState>::new();
+            builder.populate(&keys);
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (10_000_000, 0_f64);
+        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
+        println!("test_basic1 bits per entry {} bits", bpv);
+        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
+
+        for _ in 0..falsesize {
+            if filter.contains(&rng.gen::<u64>()) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_basic1 false positive rate {}%", fpp);
+        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
+    }
+
+    #[test]
+    fn test_basic2() {
+        let seed: u128 = random();
+        println!("test_basic2 seed {}", seed);
+        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+        let testsize = 1_000_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = rng.gen();
+        }
+
+        let filter = {
+            let mut builder = Xor8Builder::<RandomState>::new();
+            builder.populate_keys(&keys);
+            builder.build()
+        };
+
+        for key in keys.into_iter() {
+            assert!(filter.contains_key(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (10_000_000, 0_f64);
+        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
+        println!("test_basic2 bits per entry {} bits", bpv);
+        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
+
+        for _ in 0..falsesize {
+            if filter.contains(&rng.gen::<u64>()) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_basic2 false positive rate {}%", fpp);
+        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
+    }
+
+    #[test]
+    fn test_basic3() {
+        let mut seed: u64 = random();
+        println!("test_basic3 seed {}", seed);
+
+        let testsize = 100_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = splitmix64(&mut seed);
+        }
+
+        let filter = {
+            let mut builder = Xor8Builder::<RandomState>::new();
+            keys.iter().for_each(|key| builder.insert(key));
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (10_000_000, 0_f64);
+        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
+        println!("test_basic3 bits per entry {} bits", bpv);
+        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
+
+        for _ in 0..falsesize {
+            let v = splitmix64(&mut seed);
+            if filter.contains(&v) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_basic3 false positive rate {}%", fpp);
+        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
+    }
+
+    #[test]
+    fn test_basic4() {
+        let seed: u128 = random();
+        println!("test_basic4 seed {}", seed);
+        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+        let testsize = 1_000_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = rng.gen();
+        }
+
+        let filter = {
+            let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+            builder.populate(&keys);
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (10_000_000, 0_f64);
+        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
+        println!("test_basic4 bits per entry {} bits", bpv);
+        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
+
+        for _ in 0..falsesize {
+            if filter.contains(&rng.gen::<u64>()) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_basic4 false positive rate {}%", fpp);
+        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
+    }
+
+    #[test]
+    fn test_basic5() {
+        let seed: u128 = random();
+        println!("test_basic5 seed {}", seed);
+        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+        let testsize = 1_000_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = rng.gen();
+        }
+
+        let filter = {
+            let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+            builder.populate_keys(&keys);
+            builder.build()
+        };
+
+        for key in keys.into_iter() {
+            assert!(filter.contains_key(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (10_000_000, 0_f64);
+        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
+        println!("test_basic5 bits per entry {} bits", bpv);
+        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
+
+        for _ in 0..falsesize {
+            if filter.contains(&rng.gen::<u64>()) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_basic5 false positive rate {}%", fpp);
+        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
+    }
+
+    #[test]
+    fn test_basic6() {
+        let mut seed: u64 = random();
+        println!("test_basic6 seed {}", seed);
+
+        let testsize = 100_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = splitmix64(&mut seed);
+        }
+
+        let filter = {
+            let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+            keys.iter().for_each(|key| builder.insert(key));
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (10_000_000, 0_f64);
+        let bpv = (filter.finger_prints.len() as f64) * 8.0 / (testsize as f64);
+        println!("test_basic6 bits per entry {} bits", bpv);
+        assert!(bpv < 10.0, "bpv({}) >= 10.0", bpv);
+
+        for _ in 0..falsesize {
+            let v = splitmix64(&mut seed);
+            if filter.contains(&v) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_basic6 false positive rate {}%", fpp);
+        assert!(fpp < 0.40, "fpp({}) >= 0.40", fpp);
+    }
+
+    #[test]
+    fn test_xor16_basic1() {
+        let seed: u128 = random();
+        println!("test_xor16_basic1 seed {}", seed);
+        let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+        let testsize = 1_000_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = rng.gen();
+        }
+
+        let filter = {
+            let mut builder = Xor16Builder::<RandomState>::new();
+            builder.populate(&keys);
+            builder.build()
+        };
+
+        for key in keys.iter() {
+            assert!(filter.contains(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (10_000_000, 0_f64);
+        let bpv = (filter.finger_prints.len() as f64) * 16.0 / (testsize as f64);
+        println!("test_xor16_basic1 bits per entry {} bits", bpv);
+        assert!(bpv < 20.0, "bpv({}) >= 20.0", bpv);
+
+        for _ in 0..falsesize {
+            if filter.contains(&rng.gen::<u64>()) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_xor16_basic1 false positive rate {}%", fpp);
+        assert!(fpp < 0.005, "fpp({}) >= 0.005", fpp);
+    }
+
+    #[test]
+    fn test_xor16_basic2() {
+        let mut seed: u64 = random();
+        println!("test_xor16_basic2 seed {}", seed);
+
+        let testsize = 100_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = splitmix64(&mut seed);
+        }
+
+        let filter = {
+            let mut builder = Xor16Builder::<BuildHasherDefault>::new();
+            builder.populate_keys(&keys);
+            builder.build()
+        };
+
+        for key in keys.into_iter() {
+            assert!(filter.contains_key(key), "key {} not present", key);
+        }
+
+        let (falsesize, mut matches) = (1_000_000, 0_f64);
+        for _ in 0..falsesize {
+            let v = splitmix64(&mut seed);
+            if filter.contains_key(v) {
+                matches += 1_f64;
+            }
+        }
+        let fpp = matches * 100.0 / (falsesize as f64);
+        println!("test_xor16_basic2 false positive rate {}%", fpp);
+        assert!(fpp < 0.01, "fpp({}) >= 0.01", fpp);
+    }
+
+    #[test]
+    fn test_xor32_basic1() {
+        let mut seed: u64 = random();
+        println!("test_xor32_basic1 seed {}", seed);
+
+        let testsize = 100_000;
+        let mut keys: Vec<u64> = Vec::with_capacity(testsize);
+        keys.resize(testsize, Default::default());
+        for key in keys.iter_mut() {
+            *key = splitmix64(&mut seed);
+        }
+
+        let filter = {
+            let mut builder = Xor32Builder::<BuildHasherDefault>::new();
+            builder.populate_keys(&keys);
+            builder.build()
+        };
+
+        for key in keys.into_iter() {
+            assert!(filter.contains_key(key), "key {} not present", key);
+        }
+
+        let bpv = (filter.finger_prints.len() as f64) * 32.0 / (testsize as f64);
+        println!("test_xor32_basic1 bits per entry {} bits", bpv);
+        assert!(bpv < 40.0, "bpv({}) >= 40.0", bpv);
+
+        let (falsesize, mut matches) = (1_000_000, 0);
+        for _ in 0..falsesize {
+            if filter.contains_key(splitmix64(&mut seed)) {
+                matches += 1;
+            }
+        }
+        println!("test_xor32_basic1 false positives {}", matches);
+        assert!(matches < 2, "matches({}) >= 2", matches);
+    }
+
+    #[test]
+    fn test_try_build() {
+        let mut seed: u64 = random();
+        println!("test_try_build seed {}", seed);
+
+        let mut keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+        builder.populate_keys(&keys);
+        let filter = builder.try_build().unwrap();
+        for key in keys.iter() {
+            assert!(filter.contains_key(*key), "key {} not present", key);
+        }
+
+        // duplicates are removed only after the first attempt fails.
+        keys.push(keys[0]);
+        let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+        builder.set_max_attempts(1).populate_keys(&keys);
+        let err = builder.clone().try_build().err().unwrap();
+        assert!(err.unpeeled >= 2, "unpeeled {}", err.unpeeled);
+        assert_eq!(builder.keys().len(), keys.len());
+
+        let mut rngcounter = 1_u64;
+        assert_eq!(err.seeds, vec![splitmix64(&mut rngcounter)]);
+
+        let res = builder.clone().try_build_keys(&[keys[0], keys[0]]);
+        assert_eq!(res.err().unwrap().unpeeled, 2);
+
+        builder.set_max_attempts(4);
+        let filter = builder.try_build().unwrap();
+        assert_eq!(filter.duplicates(), 1);
+        for key in keys.iter() {
+            assert!(filter.contains_key(*key), "key {} not present", key);
+        }
+    }
+
+    #[test]
+    fn test_set_seed() {
+        let mut seed: u64 = random();
+        println!("test_set_seed seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = Xor8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        let mut rngcounter = DEFAULT_RNG_SEED;
+        if filter.attempts() == 1 {
+            assert_eq!(filter.seed, splitmix64(&mut rngcounter));
+        }
+
+        // same keys and rng_seed yield the same filter.
+        let rng_seed = splitmix64(&mut seed);
+        let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+        builder.set_seed(rng_seed).populate_keys(&keys);
+        let filter1 = builder.clone().build();
+        let filter2 = builder.build();
+        assert!(
+            filter1 == filter2,
+            "filters differ for rng_seed {}",
+            rng_seed
+        );
+        assert_eq!(filter1.attempts(), filter2.attempts());
+        assert!(filter1.attempts() >= 1);
+
+        let mut rngcounter = rng_seed;
+        let seeds: Vec<u64> = (0..filter1.attempts())
+            .map(|_| splitmix64(&mut rngcounter))
+            .collect();
+        assert_eq!(filter1.seed, *seeds.last().unwrap());
+        for key in keys.iter() {
+            assert!(filter1.contains_key(*key), "key {} not present", key);
+        }
+
+        // failed builds report seeds drawn from rng_seed.
+        let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+        builder.set_seed(rng_seed).set_max_attempts(0);
+        let err = builder.try_build_keys(&[keys[0], keys[0]]).err().unwrap();
+        let mut rngcounter = rng_seed;
+        assert_eq!(err.seeds, vec![splitmix64(&mut rngcounter)]);
+
+        // rebuild keeps the rng_seed.
+        let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+        builder.set_seed(rng_seed).populate_keys(&keys);
+        assert!(builder.rebuild() == filter1);
+        assert!(builder.build() == filter1);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let mut seed: u64 = random();
+        println!("test_duplicate_keys seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let mut builder = Xor16Builder::<BuildHasherDefault>::new();
+        builder.populate_keys(&keys);
+        builder.populate_keys(&keys[..5000]);
+        builder.populate_keys(&keys[..100]);
+        let filter = builder.build();
+        assert_eq!(filter.duplicates(), 5100);
+        for key in keys.iter() {
+            assert!(filter.contains_key(*key), "key {} not present", key);
+        }
+
+        let filter = Xor16Builder::<BuildHasherDefault>::new()
+            .build_keys(&[keys[0], keys[0], keys[0]]);
+        assert_eq!(filter.duplicates(), 2);
+        assert!(filter.contains_key(keys[0]));
+    }
+
+    #[test]
+    fn test_filter_ref() {
+        let mut seed: u64 = random();
+        println!("test_filter_ref seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+        builder.populate(&keys);
+        let filter = builder.build();
+
+        // embed the filter inside a larger buffer.
+        let mut buf = vec![0xAB; 13];
+        buf.extend_from_slice(&filter.to_bytes());
+        buf.extend_from_slice(&[0xCD; 100]);
+
+        let fref = Xor8Ref::<BuildHasherDefault>::from_bytes(&buf[13..]).unwrap();
+        assert_eq!(fref.seed, filter.seed);
+        assert_eq!(fref.block_length, filter.block_length);
+        for key in keys.iter() {
+            assert!(fref.contains(key), "key {} not present", key);
+        }
+        for _ in 0..100_000 {
+            let key = splitmix64(&mut seed);
+            assert_eq!(fref.contains_key(key), filter.contains_key(key));
+        }
+
+        assert!(Xor8Ref::<BuildHasherDefault>::from_bytes(&buf).is_err());
+        let n = buf.len() - 101;
+        assert!(Xor8Ref::<BuildHasherDefault>::from_bytes(&buf[13..n]).is_err());
+        assert!(Xor16Ref::<BuildHasherDefault>::from_bytes(&buf[13..]).is_err());
+
+        let filter = Xor32Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        let buf = filter.to_bytes();
+        let fref = Xor32Ref::<BuildHasherDefault>::from_bytes(&buf).unwrap();
+        for key in keys.iter() {
+            assert!(fref.contains_key(*key), "key {} not present", key);
+        }
+        for _ in 0..100_000 {
+            let key = splitmix64(&mut seed);
+            assert_eq!(fref.contains_key(key), filter.contains_key(key));
+        }
+    }
+
+    #[test]
+    fn test_parallel_build() {
+        let mut seed: u64 = random();
+        println!("test_parallel_build seed {}", seed);
+
+        let mut keys: Vec<u64> = (0..1_000_000).map(|_| splitmix64(&mut seed)).collect();
+        // duplicates fail the first attempt, covering the retry.
+        keys.extend_from_within(..100);
+
+        let filter = Xor8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        assert_eq!(filter.duplicates(), 100);
+
+        for threads in [2, 3, 8] {
+            let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+            builder.set_threads(threads);
+            let pfilter = builder.build_keys(&keys);
+            assert!(
+                pfilter == filter,
+                "threads {} unequals serial build",
+                threads
+            );
+            assert_eq!(pfilter.duplicates(), 100);
+            assert_eq!(pfilter.to_bytes(), filter.to_bytes());
+        }
+
+        // fewer slots than threads.
+        let filter = Xor16Builder::<BuildHasherDefault>::new().build_keys(&keys[..5]);
+        let mut builder = Xor16Builder::<BuildHasherDefault>::new();
+        builder.set_threads(64).populate_keys(&keys[..5]);
+        let pfilter = builder.build();
+        assert!(pfilter == filter, "unequals serial build");
+    }
+
+    #[test]
+    fn test_serial_peel_compatibility() {
+        // crc32 of fingerprints built by the original serial peel, for
+        // `n` keys generated from a fixed seed.
+        let golden = [
+            (1_000, 0x785D_ADC4_u32),
+            (100_000, 0x8246_4223),
+            (1_000_000, 0xFDB0_A10C),
+        ];
+        for (n, crc) in golden.iter() {
+            let mut seed = 0x5EED_u64;
+            let keys: Vec<u64> = (0..*n).map(|_| splitmix64(&mut seed)).collect();
+            for threads in [1, 4] {
+                let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+                builder.set_threads(threads);
+                let filter = builder.build_keys(&keys);
+                assert_eq!(
+                    crc32(0, filter.finger_prints()),
+                    *crc,
+                    "n {} threads {}",
+                    n,
+                    threads
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut seed: u64 = random();
+        println!("test_rebuild seed {}", seed);
+
+        let keys: Vec<u64> = (0..30_000).map(|_| splitmix64(&mut seed)).collect();
+        let (keys1, keys2, keys3) =
+            (&keys[..10_000], &keys[10_000..20_000], &keys[20_000..]);
+
+        let filter = Xor16Builder::<BuildHasherDefault>::new().build_keys(keys1);
+
+        let mut builder = Xor16Builder::<BuildHasherDefault>::new();
+        builder.populate_keys(keys1);
+        let filter1 = builder.rebuild();
+        assert!(filter1 == filter, "rebuild unequals build");
+        assert_eq!(builder.keys().len(), keys1.len());
+
+        // keys inserted into the builder are part of the next build.
+        builder.populate_keys(&keys2[..10]);
+        builder.rebuild();
+        assert_eq!(builder.keys().len(), keys1.len() + 10);
+        builder.extend_and_rebuild(&keys2[10..]);
+        let filter1 = builder.extend_and_rebuild(keys1); // duplicates
+        assert_eq!(builder.keys().len(), keys1.len() + keys2.len());
+        assert_eq!(filter1.duplicates(), 0);
+        for key in keys[..20_000].iter() {
+            assert!(filter1.contains_key(*key), "key {} not present", key);
+        }
+
+        let mut builder2 = Xor16Builder::<BuildHasherDefault>::new();
+        builder2.populate_keys(keys3);
+        builder.merge(&builder2);
+        let filter1 = builder.rebuild();
+        assert_eq!(builder.keys().len(), keys.len());
+        for key in keys.iter() {
+            assert!(filter1.contains_key(*key), "key {} not present", key);
+        }
+
+        // duplicates are removed from the builder.
+        let mut builder3 = Xor16Builder::<BuildHasherDefault>::new();
+        builder3.populate_keys(&[3, 1, 2, 1]);
+        assert_eq!(builder3.rebuild().duplicates(), 0);
+        assert_eq!(builder3.keys(), &[1, 2, 3]);
+
+        let expected = Xor16Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        assert!(filter1 == expected, "merge unequals a fresh build");
+    }
+
+    #[test]
+    fn test_contains_keys_batch() {
+        let mut seed: u64 = random();
+        println!("test_contains_keys_batch seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = Xor16Builder::<BuildHasherDefault>::new().build_keys(&keys);
+
+        // batch sizes that don't align with BATCH_SIZE.
+        let mut result = vec![false; keys.len()];
+        filter.contains_keys_batch(&keys, &mut result);
+        assert!(result.iter().all(|x| *x));
+        let mut result = vec![false; 33];
+        filter.contains_keys_batch(&keys[..33], &mut result);
+        assert!(result.iter().all(|x| *x));
+        filter.contains_keys_batch(&[], &mut []);
+
+        let probes: Vec<u64> = (0..10_001).map(|_| splitmix64(&mut seed)).collect();
+        let mut result = vec![false; probes.len()];
+        filter.contains_keys_batch(&probes, &mut result);
+        for (key, res) in probes.iter().zip(result.iter()) {
+            assert_eq!(filter.contains_key(*key), *res, "key {}", key);
+        }
+    }
+
+    #[test]
+    fn test_bytes_encoding() {
+        let mut seed: u64 = random();
+        println!("test_bytes_encoding seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let mut builder = Xor32Builder::<BuildHasherDefault>::new();
+        builder.populate_keys(&keys);
+        let filter = builder.build();
+
+        let buf = filter.to_bytes();
+        assert_eq!(buf.len(), 32 + (filter.finger_prints.len() * 4));
+        assert!(Xor8::<BuildHasherDefault>::from_bytes(buf.clone()).is_err());
+        assert!(Xor16::<BuildHasherDefault>::from_bytes(buf.clone()).is_err());
+        let filter_read = Xor32::<BuildHasherDefault>::from_bytes(buf).unwrap();
+        assert!(
+            filter_read == filter,
+            "Filter unequals after encode and decode"
+        );
+    }
+
+    #[test]
+    fn test_v1_compatibility() {
+        let mut seed: u64 = random();
+        println!("test_v1_compatibility seed {}", seed);
+
+        let keys: Vec<u64> = (0..10_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = Xor16Builder::<BuildHasherDefault>::new().build_keys(&keys);
+
+        // version 1 format, without checksum.
+        let mut buf = Xor16::<BuildHasherDefault>::SIGNATURE_V1.to_vec();
+        buf.extend_from_slice(&filter.seed.to_be_bytes());
+        buf.extend_from_slice(&filter.block_length.to_be_bytes());
+        buf.extend_from_slice(&(filter.finger_prints.len() as u32).to_be_bytes());
+        u16::encode(&filter.finger_prints, &mut buf);
+
+        let fref = Xor16Ref::<BuildHasherDefault>::from_bytes(&buf).unwrap();
+        for key in keys.iter() {
+            assert!(fref.contains_key(*key), "key {} not present", key);
+        }
+        let filter_read = Xor16::<BuildHasherDefault>::from_bytes(buf).unwrap();
+        assert!(filter_read == filter, "Filter unequals after decoding v1");
+    }
+
+    #[test]
+    fn test_write_to_read_from() {
+        let mut seed: u64 = random();
+        println!("test_write_to_read_from seed {}", seed);
+
+        // more fingerprints than a single chunk.
+        let keys: Vec<u64> = (0..100_000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter1 =
+            Xor16Builder::<BuildHasherDefault>::new().build_keys(&keys[..50_000]);
+        let filter2 =
+            Xor16Builder::<BuildHasherDefault>::new().build_keys(&keys[50_000..]);
+
+        // two filters back to back in the same stream.
+        let mut buf: Vec<u8> = vec![];
+        let n1 = filter1.write_to(&mut buf).unwrap();
+        assert_eq!(buf, filter1.to_bytes());
+        let n2 = filter2.write_to(&mut buf).unwrap();
+        assert_eq!(buf.len(), n1 + n2);
+
+        let mut r = &buf[..];
+        let filter_read = Xor16::<BuildHasherDefault>::read_from(&mut r).unwrap();
+        assert!(filter_read == filter1, "Filter unequals after read_from");
+        let filter_read = Xor16::<BuildHasherDefault>::read_from(&mut r).unwrap();
+        assert!(filter_read == filter2, "Filter unequals after read_from");
+        assert!(r.is_empty());
+        for key in keys[50_000..].iter() {
+            assert!(filter_read.contains_key(*key), "key {} not present", key);
+        }
+
+        // version 1 format, without checksum.
+        let mut buf = Xor16::<BuildHasherDefault>::SIGNATURE_V1.to_vec();
+        buf.extend_from_slice(&filter1.seed.to_be_bytes());
+        buf.extend_from_slice(&filter1.block_length.to_be_bytes());
+        buf.extend_from_slice(&(filter1.finger_prints.len() as u32).to_be_bytes());
+        u16::encode(&filter1.finger_prints, &mut buf);
+        let filter_read = Xor16::<BuildHasherDefault>::read_from(&buf[..]).unwrap();
+        assert!(filter_read == filter1, "Filter unequals after decoding v1");
+
+        // truncated and damaged streams.
+        let buf = filter1.to_bytes();
+        for n in [0, 3, 10, 23, 30, buf.len() / 2, buf.len() - 1] {
+            let err = Xor16::<BuildHasherDefault>::read_from(&buf[..n]).err();
+            assert!(
+                matches!(err, Some(Error::Truncated { .. })),
+                "{} {:?}",
+                n,
+                err
+            );
+        }
+        for off in [buf.len() - 1, 40, 5] {
+            let mut cbuf = buf.clone();
+            cbuf[off] ^= 0x10;
+            let err = Xor16::<BuildHasherDefault>::read_from(&cbuf[..]).err();
+            assert!(
+                matches!(err, Some(Error::ChecksumMismatch { .. })),
+                "{:?}",
+                err
+            );
+        }
+        let err = Xor8::<BuildHasherDefault>::read_from(&buf[..]).err();
+        assert!(matches!(err, Some(Error::InvalidSignature(_))), "{:?}", err);
+        let mut cbuf = buf.clone();
+        cbuf[12..16].copy_from_slice(&0_u32.to_be_bytes());
+        let err = Xor16::<BuildHasherDefault>::read_from(&cbuf[..]).err();
+        assert!(matches!(err, Some(Error::ZeroBlockLength)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_corrupt_bytes() {
+        let mut seed: u64 = random();
+        println!("test_corrupt_bytes seed {}", seed);
+
+        let keys: Vec<u64> = (0..1000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = Xor8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        let buf = filter.to_bytes();
+
+        let check = |buf: Vec<u8>, f: fn(&Error) -> bool, kind: io::ErrorKind| {
+            let err = Xor8Ref::<BuildHasherDefault>::from_bytes(&buf)
+                .err()
+                .unwrap();
+            assert!(f(&err), "{}", err);
+            let err = Xor8::<BuildHasherDefault>::from_bytes(buf).err().unwrap();
+            assert!(f(&err), "{}", err);
+            assert_eq!(io::Error::from(err).kind(), kind);
+        };
+
+        // flip a bit in the fingerprints, then in the header.
+        for off in [buf.len() - 1, 40, 5] {
+            let mut cbuf = buf.clone();
+            cbuf[off] ^= 0x10;
+            check(
+                cbuf,
+                |err| matches!(err, Error::ChecksumMismatch { .. }) && err.is_corrupt(),
+                io::ErrorKind::InvalidData,
+            );
+        }
+        // truncated file.
+        for n in [0, 3, 10, 23, buf.len() - 1] {
+            check(
+                buf[..n].to_vec(),
+                |err| matches!(err, Error::Truncated { .. }) && err.is_corrupt(),
+                io::ErrorKind::UnexpectedEof,
+            );
+        }
+        // not a filter.
+        let mut cbuf = buf.clone();
+        cbuf[0] = b'{';
+        check(
+            cbuf,
+            |err| matches!(err, Error::InvalidSignature(_)) && err.is_corrupt(),
+            io::ErrorKind::InvalidData,
+        );
+        // filter of another fingerprint width, or another filter type.
+        let filter = Xor16Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        check(
+            filter.to_bytes(),
+            |err| matches!(err, Error::InvalidSignature(_)) && !err.is_corrupt(),
+            io::ErrorKind::InvalidData,
+        );
+        let filter =
+            crate::BinaryFuse8Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        check(
+            filter.to_bytes(),
+            |err| matches!(err, Error::InvalidSignature(_)) && !err.is_corrupt(),
+            io::ErrorKind::InvalidData,
+        );
+        // unknown version.
+        let mut cbuf = buf.clone();
+        cbuf[3] = 9;
+        check(
+            cbuf,
+            |err| matches!(err, Error::UnsupportedVersion(9)) && !err.is_corrupt(),
+            io::ErrorKind::Unsupported,
+        );
+    }
+
+    #[test]
+    fn test_strict_validation() {
+        let mut seed: u64 = random();
+        println!("test_strict_validation seed {}", seed);
+
+        let keys: Vec<u64> = (0..1000).map(|_| splitmix64(&mut seed)).collect();
+
+        let filter = Xor16Builder::<BuildHasherDefault>::new().build_keys(&keys);
+        let buf = filter.to_bytes();
+
+        let set_header = |block_length: u32, fp_len: u32| {
+            let mut cbuf = buf.clone();
+            cbuf[12..16].copy_from_slice(&block_length.to_be_bytes());
+            cbuf[16..20].copy_from_slice(&fp_len.to_be_bytes());
+            cbuf
+        };
+        let check = |buf: Vec<u8>, f: fn(&Error) -> bool| {
+            let err = Xor16Ref::<BuildHasherDefault>::from_bytes(&buf)
+                .err()
+                .unwrap();
+            assert!(f(&err), "{}", err);
+            let err = Xor16::<BuildHasherDefault>::from_bytes(buf).err().unwrap();
+            assert!(f(&err), "{}", err);
+        };
+
+        let (block_length, fp_len) = (filter.block_length, filter.block_length * 3);
+        let mismatch = |err: &Error| matches!(err, Error::BlockLengthMismatch { .. });
+        check(set_header(block_length + 1, fp_len), mismatch);
+        check(set_header(block_length, fp_len - 1), mismatch);
+        let zero = |err: &Error| matches!(err, Error::ZeroBlockLength);
+        check(set_header(0, fp_len), zero);
+        check(set_header(0, 0), zero);
+        let truncated = |err: &Error| matches!(err, Error::Truncated { .. });
+        check(set_header(block_length + 1, fp_len + 3), truncated);
+        check(set_header(u32::MAX / 3, (u32::MAX / 3) * 3), truncated);
+
+        // trailing bytes are rejected by owned filter, ignored by the view.
+        let mut cbuf = buf.clone();
+        cbuf.push(0);
+        assert!(Xor16Ref::<BuildHasherDefault>::from_bytes(&cbuf).is_ok());
+        let err = Xor16::<BuildHasherDefault>::from_bytes(cbuf).err().unwrap();
+        assert!(matches!(err, Error::TrailingBytes(1)), "{}", err);
+
+        // version 1 has no checksum, and can be shorter than its header.
+        let mut v1 = b"^TL\x01".to_vec();
+        v1.extend_from_slice(&splitmix64(&mut seed).to_be_bytes());
+        v1.extend_from_slice(&1_u32.to_be_bytes());
+        v1.extend_from_slice(&3_u32.to_be_bytes());
+        v1.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(v1.len(), 23);
+        let fref = Xor8Ref::<BuildHasherDefault>::from_bytes(&v1).unwrap();
+        assert_eq!(fref.block_length, 1);
+        let filter = Xor8::<BuildHasherDefault>::from_bytes(v1).unwrap();
+        assert_eq!(filter.finger_prints, vec![1, 2, 3]);
+
+        // damaged headers and fingerprints must never panic.
+        let n = buf.len();
+        for off in (0..24).chain([n / 2, n - 1]) {
+            for bit in 0..8 {
+                let mut cbuf = buf.clone();
+                cbuf[off] ^= 1 << bit;
+                if let Ok(fref) = Xor16Ref::<BuildHasherDefault>::from_bytes(&cbuf) {
+                    keys.iter().for_each(|key| {
+                        fref.contains_key(*key);
+                    });
+                }
+                if let Ok(filter) = Xor16::<BuildHasherDefault>::from_bytes(cbuf) {
+                    keys.iter().for_each(|key| {
+                        filter.contains_key(*key);
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(0, b""), 0);
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926);
+    }
+}
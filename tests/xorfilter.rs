@@ -1,9 +1,11 @@
 use rand::{prelude::random, rngs::SmallRng, Rng, SeedableRng};
 use std::collections::hash_map::RandomState;
-use xorfilter::Xor8;
+use xorfilter::{
+    BuildHasherDefault, BuildSipHasher13, Error, Xor16, Xor16Builder, Xor8, Xor8Builder,
+};
 
 /// Generate a filter with random keys
-fn generate_filter() -> Xor8<RandomState> {
+fn generate_filter() -> Xor8<BuildHasherDefault> {
     let seed: u128 = random();
     println!("seed {}", seed);
     let mut rng = SmallRng::from_seed(seed.to_le_bytes());
@@ -14,10 +16,9 @@ fn generate_filter() -> Xor8<RandomState> {
     for key in keys.iter_mut() {
         *key = rng.gen();
     }
-    let mut filter = Xor8::<RandomState>::new();
-    filter.populate(&keys);
-    filter.build();
-    filter
+    let mut builder = Xor8Builder::<BuildHasherDefault>::new();
+    builder.populate(&keys);
+    builder.build()
 }
 
 #[test]
@@ -81,9 +82,9 @@ fn test_string_keys() {
         "learning curves are a blessing in disguise",
     ];
     let hash_builder = RandomState::new();
-    let mut filter = Xor8::with_hasher(hash_builder);
-    filter.populate(&rust_tips);
-    filter.build();
+    let mut builder = Xor8Builder::with_hasher(hash_builder);
+    builder.populate(&rust_tips);
+    let filter = builder.build();
 
     // Test all keys(rust_tips)
     for tip in rust_tips {
@@ -94,3 +95,37 @@ fn test_string_keys() {
     // String not in keys(rust_tips)
     assert!(!filter.contains("No magic, just code"));
 }
+
+#[test]
+fn test_xor16_bytes_encoding() {
+    let seed: u128 = random();
+    println!("seed {}", seed);
+    let mut rng = SmallRng::from_seed(seed.to_le_bytes());
+
+    let keys: Vec<u64> = (0..10000).map(|_| rng.gen()).collect();
+    let hash_builder = BuildSipHasher13::new(rng.gen(), rng.gen());
+    let mut builder = Xor16Builder::with_hasher(hash_builder.clone());
+    builder.populate(&keys);
+    let filter = builder.build();
+
+    let buf = filter.to_bytes();
+    let filter_read = Xor16::<BuildSipHasher13>::from_bytes(buf)
+        .unwrap_or_else(|err| panic!("Read from bytes failed {}", err));
+    assert!(
+        filter_read == filter,
+        "Filter unequals after encode and decode"
+    );
+    assert_eq!(*filter_read.hash_builder(), hash_builder);
+    for key in keys.iter() {
+        assert!(filter_read.contains(key), "key {} not present", key);
+    }
+    assert!(
+        Xor8::<BuildSipHasher13>::from_bytes(filter.to_bytes()).is_err(),
+        "Xor16 bytes should not decode as Xor8"
+    );
+    match Xor16::<BuildHasherDefault>::from_bytes(filter.to_bytes()) {
+        Err(Error::HasherMismatch { .. }) => (),
+        Err(err) => panic!("unexpected error {}", err),
+        Ok(_) => panic!("keyed filter should not decode with default hasher"),
+    }
+}